@@ -0,0 +1,63 @@
+//! Benchmark of FastDTW on the nanopore reads in src/testdata.
+//! Run by `cargo bench`. Each case reports the median time of a call.
+extern crate dtw;
+use dtw::{dtw, normalize, Mode, NormalizeType};
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: usize = 7;
+
+// The mean current of each event, z-normalized.
+fn read_eve(path: &std::path::Path) -> Vec<f32> {
+    let f = std::fs::File::open(path).unwrap();
+    let events: Vec<f32> = std::io::BufReader::new(f)
+        .lines()
+        .skip(1)
+        .filter_map(|e| e.ok())
+        .filter_map(|line| line.split(',').nth(2).and_then(|e| e.parse().ok()))
+        .collect();
+    normalize(&events, NormalizeType::Z)
+}
+
+fn median_time<F: FnMut()>(mut f: F) -> Duration {
+    let mut times: Vec<_> = (0..ITERATIONS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[ITERATIONS / 2]
+}
+
+fn main() {
+    let mut paths: Vec<_> = std::fs::read_dir("./src/testdata/")
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect();
+    paths.sort();
+    let mut reads: Vec<_> = paths.iter().map(|p| read_eve(p)).collect();
+    // The two longest reads are used.
+    reads.sort_by_key(|r| std::cmp::Reverse(r.len()));
+    let dist = |x: &f32, y: &f32| (x - y).powi(2);
+    for &(query_len, reference_len) in &[(1000, 1000), (2000, 5000), (5000, 18000)] {
+        let query = &reads[1][..query_len];
+        let reference = &reads[0][..reference_len];
+        for &radius in &[10, 50] {
+            for &mode in &[Mode::Fast(radius), Mode::FastSub(radius)] {
+                let time = median_time(|| {
+                    dtw(query, reference, mode, &dist).unwrap();
+                });
+                println!(
+                    "{:>12} query:{:>5} reference:{:>6} {:>10.3}ms",
+                    mode.to_string(),
+                    query_len,
+                    reference_len,
+                    time.as_secs_f64() * 1000.
+                );
+            }
+        }
+    }
+}
@@ -1,233 +1,247 @@
+use super::dtw_itakura::region_dtw;
+use super::Alignment;
+use super::DtwError;
 use num::Float;
-#[inline]
-fn dp_to_matrix_idx(x: usize, band: usize) -> (usize, usize) {
-    //    println!("{}->({},{})",x,x/band,x/band + x % band - (band-1)/2);
-    (x / band, x / band + x % band - (band - 1) / 2)
-}
-#[test]
-fn dp_to_matrix_test() {
-    assert_eq!(dp_to_matrix_idx(4, 3), (1, 1));
-    assert_eq!(dp_to_matrix_idx(10, 3), (3, 3));
-    assert_eq!(dp_to_matrix_idx(22, 3), (7, 7));
-    assert_eq!(dp_to_matrix_idx(22, 5), (4, 4));
-    assert_eq!(dp_to_matrix_idx(26, 5), (5, 4));
-    assert_eq!(dp_to_matrix_idx(4, 5), (0, 2));
-}
-#[inline]
-fn matrix_to_dp_idx(i: usize, j: usize, band: usize) -> usize {
-    //    println!("({},{}) -> {}",i,j,band*i + j - i + band/2);
-    band * i + j - i + band / 2
-}
-#[test]
-fn matrix_to_dp_test() {
-    assert_eq!(matrix_to_dp_idx(0, 0, 3), 1);
-    assert_eq!(matrix_to_dp_idx(7, 7, 3), 22);
-    assert_eq!(matrix_to_dp_idx(4, 3, 3), 12);
-    assert_eq!(matrix_to_dp_idx(5, 5, 5), 27);
-    assert_eq!(matrix_to_dp_idx(3, 5, 5), 19);
-    assert_eq!(matrix_to_dp_idx(1, 3, 5), 9);
-}
 
-#[test]
-fn inversivity() {
-    let band = 11;
-    let n = 20;
-    for i in 0..n {
-        for j in 0..n {
-            if j < i + band / 2 && i < j + band / 2 {
-                let dp = matrix_to_dp_idx(i, j, band);
-                let (n, m) = dp_to_matrix_idx(dp, band);
-                assert_eq!((i, j), (n, m));
-            }
-        }
+// Column range [start,end] (0-based,inclusive) of the reference for each query position.
+// The band follows the rescaled diagonal from (0,0) to (x1len-1,x2len-1),
+// and the cells at most band/2 columns away from it are in the band.
+// Each range is widened if needed so that (0,0) and (x1len-1,x2len-1) are always connected.
+pub(crate) fn chiba_region(x1len: usize, x2len: usize, band: usize) -> Vec<(usize, usize)> {
+    let last_row = x1len - 1;
+    let last_col = x2len - 1;
+    if last_row == 0 {
+        return vec![(0, last_col)];
+    }
+    let slope = last_col as f64 / last_row as f64;
+    let half = band / 2;
+    let mut region: Vec<(usize, usize)> = (0..x1len)
+        .map(|i| {
+            let diagonal = (slope * i as f64).round() as usize;
+            let start = diagonal.saturating_sub(half);
+            let end = (diagonal + half).min(last_col);
+            (start, end)
+        })
+        .collect();
+    region[0].0 = 0;
+    region[last_row].1 = last_col;
+    for i in 1..x1len {
+        // (i-1,end) -> (i,end+1) should be a valid transition.
+        region[i].0 = region[i].0.min(region[i - 1].1 + 1);
     }
+    region
 }
 
 #[test]
-fn inversivity2() {
-    let band = 21;
-    let n = 30;
-    for dp in 0..band * n {
-        if dp_valid_idx(dp, band, n) {
-            let (i, j) = dp_to_matrix_idx(dp, band);
-            let m = matrix_to_dp_idx(i, j, band);
-            debug_assert!(dp == m, "{} -> ({},{}) -> {}", dp, i, j, m);
-        }
-    }
-}
-#[inline]
-fn dp_valid_idx(x: usize, band: usize, len: usize) -> bool {
-    x / band + x % band <= (band - 1) / 2 + len && x / band + x % band > (band - 1) / 2
+fn chiba_region_test() {
+    let region = chiba_region(5, 5, 3);
+    assert_eq!(region, vec![(0, 1), (0, 2), (1, 3), (2, 4), (3, 4)]);
+    let region = chiba_region(3, 5, 3);
+    assert_eq!(region, vec![(0, 1), (1, 3), (3, 4)]);
+    let region = chiba_region(1, 10, 3);
+    assert_eq!(region, vec![(0, 9)]);
 }
 
-fn nextpos<D, F, T>(
-    x1: &[D],
-    x2: &[D],
-    dp: &[T],
-    x1pos: usize,
-    x2pos: usize,
-    dist: &F,
-    band: usize,
-) -> (usize, usize)
-where
-    F: Fn(&D, &D) -> T,
-    T: Float,
-{
-    let current_score =
-        dp[matrix_to_dp_idx(x1pos, x2pos, band)] - dist(&x1[x1pos - 1], &x2[x2pos - 1]);
-    let match_path = dp[matrix_to_dp_idx(x1pos - 1, x2pos - 1, band)] - current_score;
-    let gap_path = dp[matrix_to_dp_idx(x1pos, x2pos - 1, band)] - current_score;
-    let del_path = dp[matrix_to_dp_idx(x1pos - 1, x2pos, band)] - current_score;
-    if match_path < gap_path && match_path < del_path {
-        (x1pos - 1, x2pos - 1)
-    } else if gap_path < match_path && gap_path < del_path {
-        (x1pos, x2pos - 1)
-    } else {
-        (x1pos - 1, x2pos)
+#[test]
+fn chiba_region_connected() {
+    for &(x1len, x2len) in &[(10, 10), (10, 40), (40, 10), (2, 100), (100, 2), (37, 53)] {
+        for &band in &[3, 11, 1001] {
+            let region = chiba_region(x1len, x2len, band);
+            assert_eq!(region.len(), x1len);
+            assert_eq!(region[0].0, 0);
+            assert_eq!(region[x1len - 1].1, x2len - 1);
+            for w in region.windows(2) {
+                let ((s1, e1), (s2, e2)) = (w[0], w[1]);
+                assert!(s1 <= e1 && s2 <= e2, "{:?}", region);
+                assert!(s1 <= s2 && e1 <= e2 && s2 <= e1 + 1, "{:?}", region);
+            }
+        }
     }
 }
 
 #[inline]
-fn detect_err_about_band(x1len: usize, x2len: usize, band: usize) -> Result<(), String> {
+pub(crate) fn detect_err_about_band(band: usize) -> Result<(), DtwError> {
     match band {
-        n if n == 1 => Err("Band width should be greater than or equal to 3".to_string()),
-        n if n % 2 == 0 => Err("Band width should be an odd number".to_string()),
-        n if n > x1len || n > x2len => {
-            Err("Band width should be smaller than query length".to_string())
-        }
-        _ if x1len != x2len => Err("The two sequence is not the same length".to_string()),
+        n if n == 1 || n % 2 == 0 => Err(DtwError::InvalidBand(n)),
         _ => Ok(()),
     }
 }
 
 #[test]
 fn err_detect1() {
-    let x1len = 10;
-    let x2len = 10;
-    let band = 3;
-    assert!(detect_err_about_band(x1len, x2len, band).is_ok());
+    assert!(detect_err_about_band(3).is_ok());
 }
 #[test]
 fn err_detect2() {
-    let x1len = 10;
-    let x2len = 10;
-    let band = 1;
-    assert!(detect_err_about_band(x1len, x2len, band).is_err());
+    assert!(detect_err_about_band(1).is_err());
 }
 #[test]
 fn err_detect3() {
-    let x1len = 10;
-    let x2len = 10;
-    let band = 4;
-    assert!(detect_err_about_band(x1len, x2len, band).is_err());
+    assert_eq!(detect_err_about_band(4), Err(DtwError::InvalidBand(4)));
 }
 #[test]
-fn err_detect4() {
-    let x1len = 10;
-    let x2len = 10;
-    let band = 11;
-    assert!(detect_err_about_band(x1len, x2len, band).is_err());
+fn band_wider_than_series() {
+    // It used to be BandTooWide. Now the band covers the whole matrix.
+    let x: Vec<_> = (0..10).collect();
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = dtw_chiba(&x, &x, &d, 11, f32::INFINITY).unwrap();
+    assert_eq!(aln, super::dtw(&x, &x, super::Mode::Full, &d).unwrap());
 }
 #[test]
-fn err_detect5() {
-    let x1len = 201;
-    let x2len = 200;
-    let band = 100;
-    assert!(detect_err_about_band(x1len, x2len, band).is_err());
+fn different_lengths() {
+    // It used to be LengthMismatch.
+    let x1: Vec<_> = (0..201).collect();
+    let x2: Vec<_> = (0..200).collect();
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    assert!(dtw_chiba(&x1, &x2, &d, 101, f32::INFINITY).is_ok());
+    assert!(dtw_chiba(&x1, &x2, &d, 100, f32::INFINITY).is_err());
 }
 
-#[allow(dead_code)]
-fn dpflush<T>(dp: &Vec<T>, band: usize, n: usize)
+/// Dynamic time warping restricted to Sakoe-Chiba band.
+/// The band follows the rescaled diagonal, thus the two series may have different lengths.
+/// Cells at most band/2 reference positions away from the diagonal are allowed.
+/// The band should be an odd number greater than or equal to 3.
+/// If the band covers the whole matrix, the result is the same as Mode::Full.
+/// It is abandoned when every cell of a row exceeds cutoff.
+pub fn dtw_chiba<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    band: usize,
+    cutoff: T,
+) -> Result<Alignment<T>, DtwError>
 where
+    F: Fn(&D, &D) -> T,
     T: Float,
 {
-    println!();
-    for i in 0..n + 1 {
-        for j in 0..n + 1 {
-            if j <= i + band / 2 && i <= j + band / 2 {
-                let x = dp[matrix_to_dp_idx(i, j, band)];
-                if x.is_infinite() {
-                    print!("iii");
-                } else {
-                    print!("{:3.0}", dp[matrix_to_dp_idx(i, j, band)].to_f32().unwrap());
-                };
-            } else {
-                print!("eee");
-            }
-        }
-        println!();
+    detect_err_about_band(band)?;
+    if x1.is_empty() || x2.is_empty() {
+        return Err(DtwError::EmptyInput);
     }
-    // for i in 0..dp.len(){
-    //     if i % band == 0{
-    //         println!();
-    //     }
-    //     print!("{:3}",dp[i].to_f32().unwrap());
-    // }
+    let region = chiba_region(x1.len(), x2.len(), band);
+    region_dtw(x1, x2, dist, &region, cutoff)
 }
 
-type TRACE = (Vec<usize>, Vec<usize>);
-pub fn dtw_chiba<D, F, T>(
+/// Subsequence search with Sakoe-Chiba band.
+/// The query is aligned to every window of the reference with the same length
+/// by dtw_chiba, and the best window is reported with its path.
+/// The DP is computed from scratch for every window, so it takes O((n-m+1)*m*band) time
+/// for a query of length m and a reference of length n. Only the local cost of each cell
+/// is computed once and shared by the adjacent windows, and a window is abandoned as soon as
+/// it can not be better than the best one so far. Thus, dist should be nonnegative.
+/// On ties, the leftmost window is reported.
+/// If the reference is shorter than the query, the whole reference is aligned as dtw_chiba does.
+/// Only a window whose score is at most cutoff is reported. Until one is found, a window is
+/// abandoned when every cell of a row exceeds cutoff, and the deepest row reached
+/// is reported when every window is abandoned.
+pub fn sub_dtw_chiba<D, F, T>(
     x1: &[D],
     x2: &[D],
     dist: &F,
     band: usize,
-) -> Result<(T, TRACE, usize), String>
+    cutoff: T,
+) -> Result<Alignment<T>, DtwError>
 where
     F: Fn(&D, &D) -> T,
     T: Float,
 {
-    detect_err_about_band(x1.len(), x2.len(), band)?;
-    let n = x1.len();
-    let mut dp = vec![num::zero(); (n + 1) * band];
-    for i in 0..(band + 1) / 2 {
-        dp[matrix_to_dp_idx(i, 0, band)] = Float::infinity();
+    detect_err_about_band(band)?;
+    let (x1len, x2len) = (x1.len(), x2.len());
+    if x1len == 0 || x2len == 0 {
+        return Err(DtwError::EmptyInput);
     }
-    for j in 0..(band + 1) / 2 {
-        dp[matrix_to_dp_idx(0, j, band)] = Float::infinity();
+    if x2len <= x1len {
+        return dtw_chiba(x1, x2, dist, band, cutoff);
     }
-    for i in (band + 1) / 2..n + 1 {
-        dp[matrix_to_dp_idx(i, i - (band - 1) / 2, band)] = Float::infinity();
-    }
-    for j in (band + 1) / 2..n + 1 {
-        dp[matrix_to_dp_idx(j - (band - 1) / 2, j, band)] = Float::infinity();
-    }
-    dp[matrix_to_dp_idx(0, 0, band)] = num::zero();
-    // dynamic time warping
-    //dpflush(&dp,band,n);
-    for idx in (band / 2) + band..(n + 1) * band {
-        let current: T = dp[idx];
-        if !current.is_infinite() && dp_valid_idx(idx, band, n) {
-            let (i, j) = dp_to_matrix_idx(idx, band);
-            let prev1 = matrix_to_dp_idx(i - 1, j - 1, band);
-            let prev2 = matrix_to_dp_idx(i, j - 1, band);
-            let prev3 = matrix_to_dp_idx(i - 1, j, band);
-            let cost = dist(&x1[i - 1], &x2[j - 1]);
-            let min: T = if dp[prev2] < dp[prev3] && dp[prev2] < dp[prev1] {
-                dp[prev2]
-            } else if dp[prev3] < dp[prev2] && dp[prev3] < dp[prev1] {
-                dp[prev3]
+    let half = (band / 2).min(x1len - 1);
+    let width = 2 * half + 1;
+    let region = chiba_region(x1len, x1len, width);
+    // diagonals[(j + half - i) % width][i] is the local cost of (i,j).
+    // Window s uses the diagonals j - i = s - half ..= s + half.
+    let mut diagonals = vec![vec![T::infinity(); x1len]; width];
+    let fill = |diagonals: &mut [Vec<T>], shifted: usize| {
+        let costs = &mut diagonals[shifted % width];
+        for (i, cost) in costs.iter_mut().enumerate() {
+            *cost = if half <= i + shifted && i + shifted < x2len + half {
+                dist(&x1[i], &x2[i + shifted - half])
             } else {
-                dp[prev1]
+                T::infinity()
             };
-            dp[idx] = if !min.is_infinite() { cost + min } else { min };
         }
+    };
+    for shifted in 0..width - 1 {
+        fill(&mut diagonals, shifted);
     }
-    //dpflush(&dp,band,n);
-    // determine warping path
-    let mut x1path = vec![];
-    let mut x2path = vec![];
-    let mut x1pos = n;
-    let mut x2pos = n;
-    while x1pos > 0 {
-        x1path.push(x1pos - 1);
-        x2path.push(x2pos - 1);
-        let next_pos = nextpos(x1, x2, &dp, x1pos, x2pos, dist, band);
-        x1pos = next_pos.0;
-        x2pos = next_pos.1;
+    let mut previous = vec![T::infinity(); x1len];
+    let mut current = vec![T::infinity(); x1len];
+    let mut best: Option<(T, usize)> = None;
+    let mut deepest = 0;
+    for offset in 0..=x2len - x1len {
+        fill(&mut diagonals, offset + width - 1);
+        // Until a window is found, the scores up to cutoff are acceptable.
+        let is_over = |score: T| match best {
+            Some((best, _)) => score >= best,
+            None => score > cutoff || score.is_infinite(),
+        };
+        let mut is_abandoned = false;
+        for (i, &(start, end)) in region.iter().enumerate() {
+            let mut row_min = T::infinity();
+            for k in start..=end {
+                let min = if i == 0 && k == 0 {
+                    T::zero()
+                } else {
+                    let prev = if i > 0 { region[i - 1] } else { (1, 0) };
+                    let mat = if k > prev.0 && k <= prev.1 + 1 {
+                        previous[k - 1]
+                    } else {
+                        T::infinity()
+                    };
+                    let gap = if k > start {
+                        current[k - 1]
+                    } else {
+                        T::infinity()
+                    };
+                    let del = if prev.0 <= k && k <= prev.1 {
+                        previous[k]
+                    } else {
+                        T::infinity()
+                    };
+                    mat.min(gap.min(del))
+                };
+                let cost = diagonals[(offset + k + half - i) % width][i];
+                current[k] = if min.is_infinite() { min } else { min + cost };
+                row_min = row_min.min(current[k]);
+            }
+            if is_over(row_min) {
+                is_abandoned = true;
+                deepest = deepest.max(i + 1);
+                break;
+            }
+            std::mem::swap(&mut previous, &mut current);
+        }
+        if is_abandoned {
+            continue;
+        } else if !is_over(previous[x1len - 1]) {
+            best = Some((previous[x1len - 1], offset));
+        } else {
+            deepest = x1len;
+        }
     }
-    x1path.reverse();
-    x2path.reverse();
-    Ok((dp[n * band + band / 2], (x1path, x2path), 0))
+    let (_, offset) = match best {
+        Some(best) => best,
+        None if cutoff.is_infinite() => return Err(DtwError::NoPath),
+        None => {
+            return Err(DtwError::Abandoned {
+                row: deepest,
+                total: x1len,
+            })
+        }
+    };
+    // Recover the path of the best window.
+    let aln = dtw_chiba(x1, &x2[offset..offset + x1len], dist, band, T::infinity())?;
+    let (x1path, x2path) = aln.path.unwrap();
+    let x2path = x2path.into_iter().map(|j| j + offset).collect();
+    Ok(Alignment::from_path(aln.score, (x1path, x2path)))
 }
 
 #[test]
@@ -236,11 +250,10 @@ fn phony_test() {
     let x2 = vec![1, 2, 3];
     let d = |x: &i32, y: &i32| (x - y).abs() as f32;
     let band = 3;
-    let (score, (xpath, ypath), start) = dtw_chiba(&x1, &x2, &d, band).unwrap();
-    assert_eq!(score, 0.0);
-    assert_eq!(xpath, vec![0, 1, 2]);
-    assert_eq!(ypath, vec![0, 1, 2]);
-    assert_eq!(start, 0);
+    let aln = dtw_chiba(&x1, &x2, &d, band, f32::INFINITY).unwrap();
+    assert_eq!(aln.score, 0.0);
+    assert_eq!(aln.path, Some((vec![0, 1, 2], vec![0, 1, 2])));
+    assert_eq!(aln.reference_start(), Some(0));
 }
 
 #[test]
@@ -253,7 +266,11 @@ fn too_large_band() {
     x2.push(0);
     let d = |x: &i32, y: &i32| (x - y).abs() as f32;
     let band = 21;
-    assert!(dtw_chiba(&x1, &x2, &d, band).is_err());
+    // The band covers the whole matrix.
+    let aln = dtw_chiba(&x1, &x2, &d, band, f32::INFINITY).unwrap();
+    let full = super::dtw(&x1, &x2, super::Mode::Full, &d).unwrap();
+    assert_eq!(aln.score, full.score);
+    assert_eq!(aln.path, full.path);
 }
 
 #[test]
@@ -262,7 +279,7 @@ fn flat_test() {
     let x2 = vec![0; 20];
     let d = |x: &i32, y: &i32| (x - y).abs() as f32;
     let band = 11;
-    let (score, _, _) = dtw_chiba(&x1, &x2, &d, band).unwrap();
+    let score = dtw_chiba(&x1, &x2, &d, band, f32::INFINITY).unwrap().score;
     assert_eq!(score, 0.);
 }
 #[test]
@@ -275,7 +292,7 @@ fn spike_test() {
     x2.append(&mut vec![10; 13]);
     let d = |x: &i32, y: &i32| (x - y).abs() as f32;
     let band = 15;
-    let (score, _, _) = dtw_chiba(&x1, &x2, &d, band).unwrap();
+    let score = dtw_chiba(&x1, &x2, &d, band, f32::INFINITY).unwrap().score;
     assert_eq!(score, 0.);
 }
 
@@ -289,9 +306,9 @@ fn short_band() {
     x2.push(0);
     let d = |x: &i32, y: &i32| (x - y).abs() as f32;
     let band = 5;
-    let (score, _, start) = dtw_chiba(&x1, &x2, &d, band).unwrap();
-    assert_eq!(score, 0.);
-    assert_eq!(start, 0);
+    let aln = dtw_chiba(&x1, &x2, &d, band, f32::INFINITY).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.reference_span, Some((0, 12)));
 }
 
 #[test]
@@ -303,7 +320,46 @@ fn band_test() {
     x2.push(0);
     let d = |x: &i32, y: &i32| (x - y).abs() as f32;
     let band = 21;
-    let (score, _, start) = dtw_chiba(&x1, &x2, &d, band).unwrap();
-    assert_eq!(score, 0.);
-    assert_eq!(start, 0);
+    let aln = dtw_chiba(&x1, &x2, &d, band, f32::INFINITY).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.reference_span, Some((0, 1010)));
+}
+
+#[test]
+fn unequal_length() {
+    let x1 = vec![0, 1, 2, 3, 4];
+    let x2 = vec![0, 0, 1, 1, 2, 2, 3, 3, 4, 4];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    for &band in &[3, 5] {
+        let aln = dtw_chiba(&x1, &x2, &d, band, f32::INFINITY).unwrap();
+        let (xpath, ypath) = aln.path.unwrap();
+        assert_eq!((xpath[0], ypath[0]), (0, 0));
+        assert_eq!((xpath[xpath.len() - 1], ypath[ypath.len() - 1]), (4, 9));
+        assert_eq!(aln.score, 0.);
+    }
+    // The query is longer than the reference.
+    let aln = dtw_chiba(&x2, &x1, &d, 3, f32::INFINITY).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.reference_span, Some((0, 5)));
+}
+
+#[test]
+fn sub_chiba_test() {
+    let x1 = vec![1, 2, 3, 3];
+    let x2 = vec![5, 5, 1, 2, 2, 5, 5, 1, 2, 3, 3, 5];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = sub_dtw_chiba(&x1, &x2, &d, 3, f32::INFINITY).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.path, Some((vec![0, 1, 2, 3], vec![7, 8, 9, 10])));
+    // On ties, the leftmost window is reported.
+    let x2 = vec![1, 2, 3, 3, 1, 2, 3, 3];
+    let aln = sub_dtw_chiba(&x1, &x2, &d, 3, f32::INFINITY).unwrap();
+    assert_eq!(aln.reference_span, Some((0, 4)));
+    // The reference is shorter than the query.
+    let aln = sub_dtw_chiba(&x1, &[1, 2, 3], &d, 3, f32::INFINITY).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.reference_span, Some((0, 3)));
+    // A single-element query.
+    let aln = sub_dtw_chiba(&[2], &x2, &d, 3, f32::INFINITY).unwrap();
+    assert_eq!(aln.reference_span, Some((1, 2)));
 }
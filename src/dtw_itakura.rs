@@ -1,16 +1,243 @@
-use super::TRACE;
+use super::Alignment;
+use super::DtwError;
 use num::Float;
-/// dynamic time warping Itakura's pentagram
+// Maximum slope of the Itakura parallelogram. The minimum slope is its reciprocal.
+const MAX_SLOPE: f64 = 2.;
+
+// Column range [start,end] (0-based,inclusive) of the reference for each query position.
+// The region is the Itakura parallelogram around the rescaled diagonal,
+// clipped so that it never goes farther than band/2 from the diagonal.
+// Each range is widened if needed so that (0,0) and (x1len-1,x2len-1) are always connected.
+fn itakura_region(x1len: usize, x2len: usize, band: usize) -> Vec<(usize, usize)> {
+    let last_row = x1len - 1;
+    let last_col = x2len - 1;
+    if last_row == 0 {
+        return vec![(0, last_col)];
+    }
+    let (m, n) = (last_row as f64, last_col as f64);
+    let slope = n / m;
+    let half = (band / 2) as f64;
+    let eps = 0.000_000_1;
+    let mut region: Vec<(usize, usize)> = (0..x1len)
+        .map(|i| {
+            let i = i as f64;
+            let diagonal = slope * i;
+            let lower = (slope / MAX_SLOPE * i)
+                .max(n - MAX_SLOPE * slope * (m - i))
+                .max(diagonal - half)
+                .max(0.);
+            let upper = (MAX_SLOPE * slope * i)
+                .min(n - slope / MAX_SLOPE * (m - i))
+                .min(diagonal + half)
+                .min(n);
+            let diagonal = diagonal.round() as usize;
+            let start = ((lower - eps).ceil() as usize).min(diagonal);
+            let end = ((upper + eps).floor() as usize).max(diagonal);
+            (start, end)
+        })
+        .collect();
+    region[0].0 = 0;
+    region[last_row].1 = last_col;
+    for i in 1..x1len {
+        // (i-1,end) -> (i,end+1) should be a valid transition.
+        region[i].0 = region[i].0.min(region[i - 1].1 + 1);
+    }
+    region
+}
+
+#[test]
+fn itakura_region_test() {
+    let region = itakura_region(5, 5, 5);
+    assert_eq!(region, vec![(0, 0), (1, 2), (1, 3), (2, 3), (4, 4)]);
+    let region = itakura_region(1, 10, 3);
+    assert_eq!(region, vec![(0, 9)]);
+}
+
+#[test]
+fn itakura_region_connected() {
+    for &(x1len, x2len) in &[(10, 10), (10, 40), (40, 10), (2, 100), (100, 2), (37, 53)] {
+        for &band in &[1, 3, 11, 1000] {
+            let region = itakura_region(x1len, x2len, band);
+            assert_eq!(region.len(), x1len);
+            assert_eq!(region[0].0, 0);
+            assert_eq!(region[x1len - 1].1, x2len - 1);
+            for w in region.windows(2) {
+                let ((s1, e1), (s2, e2)) = (w[0], w[1]);
+                assert!(s1 <= e1 && s2 <= e2, "{:?}", region);
+                assert!(s1 <= s2 && e1 <= e2 && s2 <= e1 + 1, "{:?}", region);
+            }
+        }
+    }
+}
+
+/// Dynamic time warping restricted to Itakura's parallelogram.
+/// The slope of the warping path is globally bounded by 2 and 1/2 relative to
+/// the diagonal, and the path never goes farther than band/2 from the diagonal.
+/// The two series may have different lengths.
+/// It is abandoned when every cell of a row exceeds cutoff.
 pub fn dtw_itakura<D, F, T>(
-    _x1: &[D],
-    _x2: &[D],
-    _dist: &F,
-    _band: usize,
-) -> Result<(T, TRACE, usize), String>
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    band: usize,
+    cutoff: T,
+) -> Result<Alignment<T>, DtwError>
 where
     F: Fn(&D, &D) -> T,
     T: Float,
 {
-    // impliment someday.
-    Ok((num::zero(), (vec![], vec![]), 0))
+    let x1len = x1.len();
+    let x2len = x2.len();
+    if x1len == 0 || x2len == 0 {
+        return Err(DtwError::EmptyInput);
+    }
+    let region = itakura_region(x1len, x2len, band);
+    region_dtw(x1, x2, dist, &region, cutoff)
+}
+
+// Full dynamic time warping restricted to the given column ranges.
+// The region should be connected, as the ones made by itakura_region.
+// It is abandoned when every cell of a row exceeds cutoff.
+pub(crate) fn region_dtw<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    region: &[(usize, usize)],
+    cutoff: T,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    let x1len = x1.len();
+    // dp[i][j - region[i].0] is the optimal score to reach (i,j).
+    let mut dp: Vec<Vec<T>> = Vec::with_capacity(x1len);
+    for (i, &(start, end)) in region.iter().enumerate() {
+        let mut row = Vec::with_capacity(end - start + 1);
+        for j in start..=end {
+            let min = if i == 0 && j == 0 {
+                T::zero()
+            } else {
+                let mat = if i > 0 && j > 0 {
+                    get(&dp, region, i - 1, j - 1)
+                } else {
+                    T::infinity()
+                };
+                let gap = if j > start {
+                    row[j - 1 - start]
+                } else {
+                    T::infinity()
+                };
+                let del = if i > 0 {
+                    get(&dp, region, i - 1, j)
+                } else {
+                    T::infinity()
+                };
+                mat.min(gap.min(del))
+            };
+            let cost = dist(&x1[i], &x2[j]);
+            row.push(if min.is_infinite() { min } else { min + cost });
+        }
+        if row.iter().all(|&score| score > cutoff) {
+            return Err(DtwError::Abandoned {
+                row: i + 1,
+                total: x1len,
+            });
+        }
+        dp.push(row);
+    }
+    Ok(region_path(&dp, region))
+}
+
+// The optimal warping path of the filled table of region_dtw.
+// dp[i][j - region[i].0] is the optimal score to reach (i,j).
+pub(crate) fn region_path<T: Float>(dp: &[Vec<T>], region: &[(usize, usize)]) -> Alignment<T> {
+    let (mut i, mut j) = (dp.len() - 1, region[dp.len() - 1].1);
+    let score = get(dp, region, i, j);
+    let mut x1path = vec![i];
+    let mut x2path = vec![j];
+    while i > 0 || j > 0 {
+        let mat = if i > 0 && j > 0 {
+            get(dp, region, i - 1, j - 1)
+        } else {
+            T::infinity()
+        };
+        let gap = if j > 0 {
+            get(dp, region, i, j - 1)
+        } else {
+            T::infinity()
+        };
+        let del = if i > 0 {
+            get(dp, region, i - 1, j)
+        } else {
+            T::infinity()
+        };
+        if mat <= gap && mat <= del {
+            i -= 1;
+            j -= 1;
+        } else if gap <= del {
+            j -= 1;
+        } else {
+            i -= 1;
+        }
+        x1path.push(i);
+        x2path.push(j);
+    }
+    x1path.reverse();
+    x2path.reverse();
+    Alignment::from_path(score, (x1path, x2path))
+}
+
+#[inline]
+pub(crate) fn get<T: Float>(dp: &[Vec<T>], region: &[(usize, usize)], i: usize, j: usize) -> T {
+    let (start, end) = region[i];
+    if start <= j && j <= end {
+        dp[i][j - start]
+    } else {
+        T::infinity()
+    }
+}
+
+#[test]
+fn phony_test() {
+    let x1 = vec![1, 2, 3];
+    let x2 = vec![1, 2, 3];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = dtw_itakura(&x1, &x2, &d, 3, f32::INFINITY).unwrap();
+    assert_eq!(aln.score, 0.0);
+    assert_eq!(aln.path, Some((vec![0, 1, 2], vec![0, 1, 2])));
+    assert_eq!(aln.reference_span, Some((0, 3)));
+}
+
+#[test]
+fn unequal_length() {
+    let x1 = vec![0, 1, 2, 3, 4];
+    let x2 = vec![0, 0, 1, 1, 2, 2, 3, 3, 4, 4];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = dtw_itakura(&x1, &x2, &d, 11, f32::INFINITY).unwrap();
+    let (score, (xpath, ypath)) = (aln.score, aln.path.unwrap());
+    assert_eq!((xpath[0], ypath[0]), (0, 0));
+    assert_eq!((xpath[xpath.len() - 1], ypath[ypath.len() - 1]), (4, 9));
+    let sum: f32 = xpath
+        .iter()
+        .zip(ypath.iter())
+        .map(|(&i, &j)| d(&x1[i], &x2[j]))
+        .sum();
+    assert_eq!(score, sum);
+    assert!(score <= 2.);
+}
+
+#[test]
+fn constraint_is_active() {
+    // The only zero-cost alignment needs a slope larger than 2.
+    let x1 = vec![0, 5, 5, 5, 5, 5, 5, 5];
+    let x2 = vec![0, 0, 0, 0, 0, 0, 0, 5];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = dtw_itakura(&x1, &x2, &d, 15, f32::INFINITY).unwrap();
+    let (score, (xpath, ypath)) = (aln.score, aln.path.unwrap());
+    assert!(score > 0.);
+    let region = itakura_region(x1.len(), x2.len(), 15);
+    for (&i, &j) in xpath.iter().zip(ypath.iter()) {
+        assert!(region[i].0 <= j && j <= region[i].1);
+    }
 }
@@ -0,0 +1,135 @@
+/// Error type returned by dynamic time warping functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DtwError {
+    /// The query or the reference is empty.
+    EmptyInput,
+    /// The two series should have the same length in the specified mode.
+    LengthMismatch {
+        /// Length of the query.
+        query: usize,
+        /// Length of the reference.
+        reference: usize,
+    },
+    /// The band width should be an odd number greater than or equal to 3.
+    InvalidBand(usize),
+    /// Every cell of a row exceeded the threshold, so the computation was abandoned.
+    /// `row` is the (1-based) query position where it stopped, out of `total` positions.
+    Abandoned {
+        /// Query position where the computation was abandoned.
+        row: usize,
+        /// Length of the query.
+        total: usize,
+    },
+    /// A sample has a different number of channels from the first one.
+    DimensionMismatch {
+        /// Number of channels of the first sample of the query.
+        expected: usize,
+        /// Number of channels of the offending sample.
+        found: usize,
+    },
+    /// The step pattern is not supported by the specified mode.
+    UnsupportedStepPattern,
+    /// No candidate region was found in the reference.
+    NoCandidates,
+    /// No warping path satisfies the constraints.
+    NoPath,
+    /// The window width or the number of segments of a transform is zero.
+    InvalidTransform,
+}
+
+impl std::fmt::Display for DtwError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            DtwError::EmptyInput => write!(f, "the length of given time series may be empty"),
+            DtwError::LengthMismatch { query, reference } => write!(
+                f,
+                "invalid input: the lengths are different,while restricted mode was chosen:{},{}",
+                query, reference
+            ),
+            DtwError::InvalidBand(band) => write!(
+                f,
+                "Band width should be an odd number greater than or equal to 3:{}",
+                band
+            ),
+            DtwError::Abandoned { row, total } => write!(f, "Early return:{},{}", row, total),
+            DtwError::DimensionMismatch { expected, found } => write!(
+                f,
+                "invalid input: the number of channels are different:{},{}",
+                expected, found
+            ),
+            DtwError::UnsupportedStepPattern => {
+                write!(f, "the step pattern is not supported by this mode")
+            }
+            DtwError::NoCandidates => write!(f, "There's no candidates."),
+            DtwError::NoPath => write!(f, "error occured while extracting optimal score"),
+            DtwError::InvalidTransform => {
+                write!(
+                    f,
+                    "the window width and the number of segments should be positive"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for DtwError {}
+
+/// Error type returned when a reference index is saved or loaded.
+#[derive(Debug)]
+pub enum IndexError {
+    /// Error from the file system.
+    Io(std::io::Error),
+    /// The file is not an index file, or it is truncated.
+    InvalidFormat,
+    /// The index file was written in an unsupported version of the format.
+    UnsupportedVersion(u32),
+    /// The element type of the index file is different from the requested one.
+    ElementMismatch {
+        /// Size of the requested element type in bytes.
+        expected: usize,
+        /// Size of the stored element type in bytes.
+        found: usize,
+    },
+    /// The band width of the index file is different from the requested one.
+    BandwidthMismatch {
+        /// The stored band width.
+        stored: usize,
+        /// The requested band width.
+        requested: usize,
+    },
+}
+
+impl std::fmt::Display for IndexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IndexError::Io(e) => write!(f, "{}", e),
+            IndexError::InvalidFormat => write!(f, "the file is not a valid index"),
+            IndexError::UnsupportedVersion(v) => write!(f, "unsupported index version:{}", v),
+            IndexError::ElementMismatch { expected, found } => write!(
+                f,
+                "the element size of the index is different:{},{}",
+                expected, found
+            ),
+            IndexError::BandwidthMismatch { stored, requested } => write!(
+                f,
+                "the band width of the index is different:{},{}",
+                stored, requested
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IndexError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for IndexError {
+    fn from(e: std::io::Error) -> Self {
+        IndexError::Io(e)
+    }
+}
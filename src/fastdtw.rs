@@ -1,7 +1,9 @@
-use super::TRACE;
+use super::step_pattern::{step_weights, Step};
+use super::Alignment;
+use super::DtwError;
+use super::StepPattern;
 use num::Float;
 use num::Num;
-use std::collections::HashMap;
 /// O(N) time implementation of approximate dynamic time warping.
 pub fn fast_dtw<D, F, T>(
     x1: &[D],
@@ -9,34 +11,273 @@ pub fn fast_dtw<D, F, T>(
     dist: &F,
     radius: usize,
     is_sub: bool,
-) -> Result<(T, TRACE, usize), String>
+) -> Result<Alignment<T>, DtwError>
 where
     F: Fn(&D, &D) -> T,
     T: Float,
-    D: Num + Copy, // when is_sub is true, x1 is query and x2 is reference.
+    D: Coarsen, // when is_sub is true, x1 is query and x2 is reference.
 {
-    let min_length = radius + 2;
+    fast_dtw_with_options(x1, x2, dist, is_sub, &FastDtwOptions::new(radius))
+}
+
+/// FastDTW with the specified step pattern.
+/// The same pattern is used at every resolution.
+pub fn fast_dtw_with_step<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    radius: usize,
+    is_sub: bool,
+    step: StepPattern,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    D: Coarsen,
+{
+    let options = FastDtwOptions::new(radius).step(step);
+    fast_dtw_with_options(x1, x2, dist, is_sub, &options)
+}
+
+/// FastDTW with the coarsening factor and the resolution to stop recursion
+/// given by options.
+pub fn fast_dtw_with_options<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    is_sub: bool,
+    options: &FastDtwOptions,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    D: Coarsen,
+{
+    fast_dtw_by(x1, x2, dist, is_sub, options, &reduce_by, T::infinity())
+}
+
+/// FastDTW merging consecutive samples with merge, instead of `Coarsen::coarsen`.
+/// merge is given non-empty chunks of at most `options.factor` samples.
+/// ```rust
+/// use dtw::{fast_dtw_with_merge, FastDtwOptions};
+/// let x1 = vec!["a", "b", "c", "d", "e"];
+/// let x2 = vec!["a", "a", "b", "c", "d", "e", "e"];
+/// let dist = |x: &&str, y: &&str| if x == y { 0. } else { 1. };
+/// // Keep the first sample of each chunk.
+/// let merge = |chunk: &[&'static str]| chunk[0];
+/// let options = FastDtwOptions::new(1);
+/// let aln = fast_dtw_with_merge(&x1, &x2, &dist, false, &options, &merge).unwrap();
+/// assert_eq!(aln.score, 0.);
+/// ```
+pub fn fast_dtw_with_merge<D, F, T, M>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    is_sub: bool,
+    options: &FastDtwOptions,
+    merge: &M,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    M: Fn(&[D]) -> D,
+{
+    let reduce = |xs: &[D], factor: usize| xs.chunks(factor).map(merge).collect();
+    fast_dtw_by(x1, x2, dist, is_sub, options, &reduce, T::infinity())
+}
+
+/// How FastDTW makes a coarser sample out of consecutive samples.
+/// Numbers are averaged. Implement it to run FastDTW on other data.
+/// ```rust
+/// use dtw::{dtw, Coarsen, Mode};
+/// #[derive(Debug, Clone, Copy)]
+/// struct Event {
+///     mean: f64,
+///     length: usize,
+/// }
+/// impl Coarsen for Event {
+///     fn coarsen(chunk: &[Self]) -> Self {
+///         let length: usize = chunk.iter().map(|e| e.length).sum();
+///         let sum: f64 = chunk.iter().map(|e| e.mean * e.length as f64).sum();
+///         Event {
+///             mean: sum / length as f64,
+///             length,
+///         }
+///     }
+/// }
+/// let events: Vec<_> = (0..100)
+///     .map(|i| Event {
+///         mean: (i as f64 / 10.).sin(),
+///         length: 1 + i % 3,
+///     })
+///     .collect();
+/// let dist = |x: &Event, y: &Event| (x.mean - y.mean).powi(2);
+/// let aln = dtw(&events, &events, Mode::Fast(5), &dist).unwrap();
+/// assert_eq!(aln.score, 0.);
+/// ```
+pub trait Coarsen: Sized {
+    /// Merge a non-empty chunk of consecutive samples into one.
+    fn coarsen(chunk: &[Self]) -> Self;
+}
+
+impl<D: Num + Copy> Coarsen for D {
+    fn coarsen(chunk: &[Self]) -> Self {
+        let (sum, count) = chunk
+            .iter()
+            .fold((D::zero(), D::zero()), |(sum, count), &x| {
+                (sum + x, count + D::one())
+            });
+        sum / count
+    }
+}
+
+/// Parameters of FastDTW.
+/// ```rust
+/// use dtw::{fast_dtw_with_options, FastDtwOptions};
+/// let x1 = vec![0., 1., 2., 3., 4., 3., 2., 1., 0.];
+/// let x2 = vec![0., 1., 2., 3., 3., 4., 3., 2., 1., 0., 0.];
+/// let dist = |x: &f64, y: &f64| (x - y).abs();
+/// let options = FastDtwOptions::new(1).factor(3).min_length(4);
+/// let aln = fast_dtw_with_options(&x1, &x2, &dist, false, &options).unwrap();
+/// assert_eq!(aln.score, 0.);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FastDtwOptions {
+    /// The number of cells added around the projected path.
+    pub radius: usize,
+    /// Each coarser sample is the mean of this many consecutive samples.
+    /// The last one averages the remaining samples, so no sample is dropped.
+    /// Factors smaller than 2 are treated as 2.
+    pub factor: usize,
+    /// The recursion stops and the full DP is done when either series is shorter than this.
+    /// `None` means `radius + 2`.
+    pub min_length: Option<usize>,
+    /// The step pattern used at every resolution.
+    pub step: StepPattern,
+}
+
+impl FastDtwOptions {
+    /// Halve the series at each level, the same as `fast_dtw`.
+    pub fn new(radius: usize) -> Self {
+        FastDtwOptions {
+            radius,
+            factor: 2,
+            min_length: None,
+            step: StepPattern::Symmetric1,
+        }
+    }
+    /// Set the coarsening factor.
+    pub fn factor(mut self, factor: usize) -> Self {
+        self.factor = factor;
+        self
+    }
+    /// Set the length under which the recursion stops.
+    pub fn min_length(mut self, min_length: usize) -> Self {
+        self.min_length = Some(min_length);
+        self
+    }
+    /// Set the step pattern.
+    pub fn step(mut self, step: StepPattern) -> Self {
+        self.step = step;
+        self
+    }
+    fn shrink_factor(&self) -> usize {
+        self.factor.max(2)
+    }
+    // A series of length 1 can not be shrinked any more.
+    fn stop_length(&self) -> usize {
+        self.min_length.unwrap_or(self.radius + 2).max(2)
+    }
+}
+
+// FastDTW with a custom function to make the coarser series,
+// so that it works for data without arithmetic, such as multidimensional samples.
+// reduce(xs, factor) should return ceil(xs.len()/factor) samples.
+// The DP at the finest resolution is abandoned when every cell of a row exceeds cutoff.
+pub(crate) fn fast_dtw_by<D, F, T, R>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    is_sub: bool,
+    options: &FastDtwOptions,
+    reduce: &R,
+    cutoff: T,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    R: Fn(&[D], usize) -> Vec<D>,
+{
+    if x1.is_empty() || x2.is_empty() {
+        return Err(DtwError::EmptyInput);
+    }
+    let min_length = options.stop_length();
     if x1.len() < min_length || x2.len() < min_length {
         // normal dtw.
         // full window
-        let window = (0..x1.len())
-            .map(|i| (0..x2.len()).map(|j| (i + 1, j + 1)).collect())
-            .fold(
-                Vec::with_capacity(min_length * min_length),
-                |mut acc, mut x| {
-                    acc.append(&mut x);
-                    acc
-                },
-            );
-        window_dtw(x1, x2, dist, &window, is_sub)
+        let mut window = vec![(1, x2.len() + 1); x1.len() + 1];
+        window[0] = (1, 1);
+        window_dtw(x1, x2, dist, &window, is_sub, options.step, cutoff)
     } else {
         // recursive call
-        let x1_shrinked = reduce_by_half(x1);
-        let x2_shrinked = reduce_by_half(x2);
-        let (_score, (x1path, x2path), _location) =
-            fast_dtw(&x1_shrinked, &x2_shrinked, dist, radius, is_sub)?;
-        let window = expand_window(&x1path, &x2path, x2.len(), x1.len(), radius);
-        window_dtw(x1, x2, dist, &window, is_sub)
+        let factor = options.shrink_factor();
+        let x1_shrinked = reduce(x1, factor);
+        let x2_shrinked = reduce(x2, factor);
+        // The scores of the coarser series are not bounded by cutoff.
+        let (x1path, x2path) = fast_dtw_by(
+            &x1_shrinked,
+            &x2_shrinked,
+            dist,
+            is_sub,
+            options,
+            reduce,
+            T::infinity(),
+        )?
+        .path
+        .ok_or(DtwError::NoPath)?;
+        let window = expand_window(&x1path, &x2path, x1.len(), x2.len(), options.radius, factor);
+        window_dtw(x1, x2, dist, &window, is_sub, options.step, cutoff)
+    }
+}
+
+// DP table restricted to a window.
+// The window has a contiguous range of columns [start,end) for each row,
+// and the cells of row i are stored in cells[offsets[i]..offsets[i+1]].
+// Positions are 1-based as in the DP, and row 0 is always empty.
+struct WindowTable<T> {
+    ranges: Vec<(usize, usize)>,
+    offsets: Vec<usize>,
+    // The optimal score and the index of the step reaching the cell.
+    cells: Vec<(T, usize)>,
+}
+
+impl<T: Float> WindowTable<T> {
+    fn new(ranges: &[(usize, usize)]) -> Self {
+        let mut offsets = Vec::with_capacity(ranges.len() + 1);
+        offsets.push(0);
+        for &(start, end) in ranges {
+            let last = offsets[offsets.len() - 1];
+            offsets.push(last + end.saturating_sub(start));
+        }
+        let size = offsets[ranges.len()];
+        WindowTable {
+            ranges: ranges.to_vec(),
+            offsets,
+            cells: vec![(T::infinity(), 0); size],
+        }
+    }
+    #[inline]
+    fn index(&self, i: usize, j: usize) -> Option<usize> {
+        let (start, end) = *self.ranges.get(i)?;
+        if start <= j && j < end {
+            Some(self.offsets[i] + j - start)
+        } else {
+            None
+        }
+    }
+    #[inline]
+    fn get(&self, i: usize, j: usize) -> Option<&(T, usize)> {
+        self.index(i, j).map(|idx| &self.cells[idx])
     }
 }
 
@@ -46,73 +287,64 @@ fn window_dtw<D, F, T>(
     dist: &F,
     window: &[(usize, usize)],
     is_sub: bool,
-) -> Result<(T, TRACE, usize), String>
+    step: StepPattern,
+    cutoff: T,
+) -> Result<Alignment<T>, DtwError>
 where
     F: Fn(&D, &D) -> T,
     T: Float,
-    D: Num + Copy,
 {
-    let mut dp = HashMap::new(); //dp table. This map ontains 1:optimal score,2:traceback information
-    for &(i, j) in window {
-        //        eprint!("({},{})->",i,j);
-        let d = dist(&x1[i - 1], &x2[j - 1]);
-        let paths = get_previous_scores(&dp, i, j, is_sub);
-        let opt = get_min(paths, d);
-        dp.insert((i, j), opt);
-        //        eprintln!("{:?}",opt.0.to_f32());
+    let steps = step.steps();
+    let weights: Vec<Vec<T>> = step_weights(&steps);
+    // A step skipping rows may pass no cell of a row,
+    // so that the rows can be checked only if every step moves at most one row.
+    let cutoff = if steps.iter().all(|step| step.di <= 1) {
+        cutoff
+    } else {
+        T::infinity()
+    };
+    // local cost of 1-based position.
+    let local = |i: usize, j: usize| dist(&x1[i - 1], &x2[j - 1]);
+    let mut dp = WindowTable::new(window); //dp table. Each cell contains 1:optimal score,2:the index of the step
+    for (i, &(start, end)) in window.iter().enumerate() {
+        for j in start..end {
+            let opt = get_min(&dp, i, j, is_sub, &steps, &weights, &local);
+            let idx = dp.offsets[i] + j - start;
+            dp.cells[idx] = opt;
+        }
+        let row = &dp.cells[dp.offsets[i]..dp.offsets[i + 1]];
+        if i > 0 && row.iter().all(|&(score, _)| score > cutoff) {
+            return Err(DtwError::Abandoned {
+                row: i,
+                total: x1.len(),
+            });
+        }
     }
     let (opt, max_position) = if is_sub {
-        get_optimal(&dp, &window, x1.len())
+        get_optimal(&dp, x1.len())
     } else {
         (
-            dp.get(&(x1.len(), x2.len()))
-                .ok_or_else(|| "error occured while extracting optimal score".to_string())?
-                .0,
+            dp.get(x1.len(), x2.len()).ok_or(DtwError::NoPath)?.0,
             x2.len(),
         )
     };
-    let (start_position, x1path, x2path) = get_paths(&dp, x1.len(), max_position);
-    // for (&i,&j) in x1path.iter().zip(x2path.iter()){
-    //     eprint!("({},{})->",i,j);
-    // }
-    // eprintln!("finish");
-    Ok((opt, (x1path, x2path), start_position))
-}
-
-// #[inline]
-// fn print_path(p1:&[usize],p2:&[usize]){
-//     p1.iter().zip(p2.iter()).map(|(&i,&j)| eprint!("({},{})->",i,j)).count();
-//     eprintln!();
-// }
-
-#[inline]
-fn get_previous_scores<F>(
-    dp: &HashMap<(usize, usize), (F, usize, usize)>,
-    i: usize,
-    j: usize,
-    is_sub: bool,
-) -> [(F, usize, usize); 3]
-where
-    F: Float,
-{
-    let p1 = get_score(&dp, i - 1, j - 1, is_sub); // "match" path
-    let p2 = get_score(&dp, i - 1, j, is_sub); //"insert" path
-    let p3 = get_score(&dp, i, j - 1, is_sub); //"gap" path
-    [(p1, i - 1, j - 1), (p2, i - 1, j), (p3, i, j - 1)]
+    if opt.is_infinite() {
+        return Err(DtwError::NoPath);
+    }
+    let (x1path, x2path) = get_paths(&dp, &steps, x1.len(), max_position);
+    if x1path.is_empty() {
+        return Err(DtwError::NoPath);
+    }
+    Ok(Alignment::from_path(opt, (x1path, x2path)))
 }
 
 #[inline]
-fn get_score<F>(
-    dp: &HashMap<(usize, usize), (F, usize, usize)>,
-    i: usize,
-    j: usize,
-    is_sub: bool,
-) -> F
+fn get_score<F>(dp: &WindowTable<F>, i: usize, j: usize, is_sub: bool) -> F
 where
     F: Float,
 {
     // get the optimal scores of previous cells
-    match dp.get(&(i, j)) {
+    match dp.get(i, j) {
         None if i == 0 && j == 0 && !is_sub => F::zero(), // initial score of full dtw
         None if i == 0 && is_sub => F::zero(),            // initial score of sub dtw
         None => Float::infinity(),
@@ -120,38 +352,61 @@ where
     }
 }
 
+// The optimal score at (i,j) and the index of the step reaching there.
+// Ties are resolved in the order of the steps.
 #[inline]
-fn get_min<F>(paths: [(F, usize, usize); 3], prev: F) -> (F, usize, usize)
+fn get_min<F, C>(
+    dp: &WindowTable<F>,
+    i: usize,
+    j: usize,
+    is_sub: bool,
+    steps: &[Step],
+    weights: &[Vec<F>],
+    local: &C,
+) -> (F, usize)
 where
     F: Float,
+    C: Fn(usize, usize) -> F,
 {
-    let opt = if paths[0].0 <= paths[1].0 && paths[0].0 <= paths[2].0 {
-        paths[0]
-    } else if paths[1].0 <= paths[0].0 && paths[1].0 <= paths[2].0 {
-        paths[1]
-    } else {
-        paths[2]
-    };
-    if opt.0.is_infinite() {
-        opt
-    } else {
-        (opt.0 + prev, opt.1, opt.2)
+    let here = local(i, j);
+    let mut opt = (F::infinity(), 0);
+    for (idx, (step, ws)) in steps.iter().zip(weights.iter()).enumerate() {
+        // Every visited cell should be inside the matrix.
+        if step.di > i || step.dj > j || step.cells.iter().any(|&(a, b, _)| a >= i || b >= j) {
+            continue;
+        }
+        let prev = get_score(dp, i - step.di, j - step.dj, is_sub);
+        if prev.is_infinite() {
+            continue;
+        }
+        let score = step
+            .cells
+            .iter()
+            .zip(ws.iter())
+            .filter(|&(&(_, _, w), _)| w > 0)
+            .fold(prev, |acc, (&(a, b, _), &w)| {
+                let d = if a == 0 && b == 0 {
+                    here
+                } else {
+                    local(i - a, j - b)
+                };
+                acc + w * d
+            });
+        if score < opt.0 {
+            opt = (score, idx);
+        }
     }
+    opt
 }
 
 #[inline]
-fn get_optimal<F>(
-    dp: &HashMap<(usize, usize), (F, usize, usize)>,
-    window: &[(usize, usize)],
-    x1len: usize,
-) -> (F, usize)
+fn get_optimal<F>(dp: &WindowTable<F>, x1len: usize) -> (F, usize)
 where
     F: Float,
 {
-    window
-        .iter()
-        .filter(|&&(i, _)| i == x1len)
-        .filter_map(|&(i, j)| dp.get(&(i, j)).map(|&(opt, _, _)| (opt, j)))
+    let (start, end) = dp.ranges[x1len];
+    (start..end)
+        .filter_map(|j| dp.get(x1len, j).map(|&(opt, _)| (opt, j)))
         .fold(
             (Float::infinity(), 0),
             |acc, x| if acc.0 < x.0 { acc } else { x },
@@ -159,101 +414,90 @@ where
 }
 
 #[inline]
-fn get_paths<F>(
-    dp: &HashMap<(usize, usize), (F, usize, usize)>,
-    i: usize,
-    j: usize,
-) -> (usize, Vec<usize>, Vec<usize>)
+fn get_paths<F>(dp: &WindowTable<F>, steps: &[Step], i: usize, j: usize) -> (Vec<usize>, Vec<usize>)
 where
     F: Float,
 {
+    // dp is indexed by 1-based position, while the path is 0-based.
     let (mut x1path, mut x2path) = (vec![], vec![]);
     let (mut i, mut j) = (i, j);
-    while let Some(&(_, x1, x2)) = dp.get(&(i, j)) {
-        x1path.push(x1);
-        x2path.push(x2);
-        i = x1;
-        j = x2;
+    while let Some(&(_, idx)) = dp.get(i, j) {
+        let step = &steps[idx];
+        for &(a, b, _) in &step.cells {
+            x1path.push(i - 1 - a);
+            x2path.push(j - 1 - b);
+        }
+        i -= step.di;
+        j -= step.dj;
     }
     x1path.reverse();
     x2path.reverse();
-    (j, x1path, x2path)
+    (x1path, x2path)
 }
 
-#[inline]
-pub fn reduce_by_half<D>(xs: &[D]) -> Vec<D>
-where
-    D: Num + Copy,
-{
-    let two = D::one() + D::one();
-    (0..xs.len() / 2)
-        .map(|i| (xs[2 * i] + xs[2 * i + 1]) / two)
-        .collect()
+// Coarsen each chunk of factor samples. The last chunk may be shorter.
+pub(crate) fn reduce_by<D: Coarsen>(xs: &[D], factor: usize) -> Vec<D> {
+    xs.chunks(factor).map(D::coarsen).collect()
 }
 
+// The window of the finer DP, as the 1-based column range of each row.
+// Each cell of the coarse path covers a factor x factor block of the finer matrix
+// (smaller at the tails), and the window is the union of the blocks
+// widened by radius in both directions.
 fn expand_window(
     x1path: &[usize],
     x2path: &[usize],
-    x2len: usize,
     x1len: usize,
+    x2len: usize,
     radius: usize,
+    factor: usize,
 ) -> Vec<(usize, usize)> {
     debug_assert!(x1path.len() == x2path.len());
-    //    print_path(x1path,x2path);
-    let mapped_path = x1path
-        .iter()
-        .zip(x2path.iter())
-        .map(|(&i, &j)| map_to_original(i, j))
-        .fold(vec![], |mut acc, mut x| {
-            acc.append(&mut x);
-            acc
-        });
     let mut region: Vec<Option<(usize, usize)>> = vec![None; x1len + 1];
-    for (i, j) in mapped_path {
-        let start = if i > radius { i - radius } else { 1 };
-        let end = if i + radius <= x1len {
-            i + radius
-        } else {
-            x1len + 1
-        };
-        for k in start..end {
-            let left = if j > radius { j - radius } else { 1 };
-            let right = if j + radius <= x2len {
-                j + radius
-            } else {
-                x2len + 1
-            };
-            match region[k] {
-                Some(prev) => region[k] = Some((prev.0, right)),
-                None => region[k] = Some((left, right)),
+    for (&i, &j) in x1path.iter().zip(x2path.iter()) {
+        let (rows, columns) = map_to_original(i, j, factor, x1len, x2len);
+        // 0-based rows and columns, widened.
+        let start = rows.0.saturating_sub(radius);
+        let end = (rows.1 + radius).min(x1len);
+        let left = columns.0.saturating_sub(radius) + 1;
+        let right = columns.1 + radius + 1;
+        for range in region[start + 1..end + 1].iter_mut() {
+            *range = match *range {
+                Some((l, r)) => Some((l.min(left), r.max(right))),
+                None => Some((left, right)),
             };
         }
     }
-    (1..(x1len + 1))
-        .filter_map(|k| region[k].map(|range| (k, range)))
-        .map(|(k, range)| get_window(k, range, x2len))
-        .fold(vec![], |mut acc, mut x| {
-            acc.append(&mut x);
-            acc
+    region
+        .into_iter()
+        .map(|range| {
+            range
+                .map(|range| get_window(range, x2len))
+                .unwrap_or((1, 1))
         })
+        .collect()
 }
 
+// The 0-based rows and columns, as [start,end), covered by the coarse cell (i,j).
 #[inline]
-fn map_to_original(i: usize, j: usize) -> Vec<(usize, usize)> {
-    vec![
-        (2 * i, 2 * j),
-        (2 * i + 1, 2 * j),
-        (2 * i, 2 * j + 1),
-        (2 * i + 1, 2 * j + 1),
-    ]
+fn map_to_original(
+    i: usize,
+    j: usize,
+    factor: usize,
+    x1len: usize,
+    x2len: usize,
+) -> ((usize, usize), (usize, usize)) {
+    let rows = (factor * i, (factor * i + factor).min(x1len));
+    let columns = (factor * j, (factor * j + factor).min(x2len));
+    (rows, columns)
 }
 
+// The columns in [range.0,range.1) inside the matrix.
 #[inline]
-fn get_window(k: usize, range: (usize, usize), x2len: usize) -> Vec<(usize, usize)> {
-    (range.0..range.1)
-        .filter(|&e| 0 < e && e <= x2len)
-        .map(|e| (k, e))
-        .collect()
+fn get_window(range: (usize, usize), x2len: usize) -> (usize, usize) {
+    let start = range.0.max(1);
+    let end = range.1.min(x2len + 1).max(start);
+    (start, end)
 }
 
 // #[inline]
@@ -0,0 +1,321 @@
+use super::Alignment;
+use super::DtwError;
+use num::Float;
+// Regions with at most this number of cells are solved by the plain DP with traceback.
+const BASE_CELLS: usize = 4096;
+
+/// Dynamic time warping with Hirschberg's divide-and-conquer traceback.
+/// It computes exactly the same score and path as Mode::Full (or Mode::Sub when is_sub is true),
+/// including the choice among tied paths, while it keeps only O((m+n)log(n)) scores
+/// instead of the whole table.
+/// The time complexity is still O(mn), a few times slower than the plain DP.
+/// x1 is query and x2 is reference.
+pub fn linear_dtw<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    is_sub: bool,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    let x1len = x1.len();
+    let x2len = x2.len();
+    if x1len == 0 || x2len == 0 {
+        return Err(DtwError::EmptyInput);
+    }
+    // The initial condition of Mode::Full or Mode::Sub as the scores of the row -1:
+    // top[k] is the score of the column k-1. The scores of the column -1 are infinite.
+    let mut top = vec![T::infinity(); x2len + 1];
+    if is_sub {
+        for score in top.iter_mut().take(x2len) {
+            *score = T::zero();
+        }
+    } else {
+        top[0] = T::zero();
+    }
+    let left = vec![T::infinity(); x1len];
+    // Determine the optimal score and the end position first.
+    let rows = (0, x1len - 1);
+    let mut last_row = vec![];
+    forward(
+        x1,
+        x2,
+        dist,
+        rows,
+        (0, x2len - 1),
+        &top,
+        &left,
+        |_, _, row| {
+            last_row = row.to_vec();
+        },
+    );
+    let (end, score) = if is_sub {
+        last_row
+            .iter()
+            .enumerate()
+            .skip(1)
+            .fold((0, T::infinity()), |(idx, acc), (k, &score)| {
+                if acc < score {
+                    (idx, acc)
+                } else {
+                    (k - 1, score)
+                }
+            })
+    } else {
+        (x2len - 1, last_row[x2len])
+    };
+    if score.is_infinite() {
+        return Err(DtwError::NoPath);
+    }
+    let mut cells = Vec::with_capacity(x1len + end + 1);
+    solve(
+        x1,
+        x2,
+        dist,
+        rows,
+        (0, end),
+        &top[..end + 2],
+        &left,
+        &mut cells,
+    );
+    cells.reverse();
+    Ok(Alignment::from_path(score, cells.into_iter().unzip()))
+}
+
+// Push the traceback from (rows.1, cols.1) to cells until it leaves the region.
+// top and left are the scores around the region: top[k] is the score of (rows.0-1, cols.0+k-1),
+// and left[i] is the score of (rows.0+i, cols.0-1).
+// Since every score in the region is the same as the one of the whole table,
+// so is every decision of the traceback.
+#[allow(clippy::too_many_arguments)]
+fn solve<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    rows: (usize, usize),
+    cols: (usize, usize),
+    top: &[T],
+    left: &[T],
+    cells: &mut Vec<(usize, usize)>,
+) where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    let height = rows.1 - rows.0 + 1;
+    let width = cols.1 - cols.0 + 1;
+    if height <= 2 || height * width <= BASE_CELLS {
+        return traceback(x1, x2, dist, rows, cols, top, left, cells);
+    }
+    let mid = (rows.0 + rows.1) / 2;
+    // crossings[k] = (a, b): the traceback from the k-th cell of the current row
+    // leaves the lower half at the a-th cell of the row mid, after the b-th cell of the row mid+1.
+    // a = 0 means that it leaves the region through the column -1 instead.
+    // Like the rows, the cells are indexed from the left boundary.
+    let mut mid_row = vec![];
+    let mut previous = vec![(0, 0); width + 1];
+    let mut crossings = vec![(0, 0); width + 1];
+    forward(x1, x2, dist, rows, cols, top, left, |i, above, row| {
+        if i == mid {
+            mid_row = row.to_vec();
+        }
+        if i <= mid {
+            return;
+        }
+        std::mem::swap(&mut previous, &mut crossings);
+        for k in 1..=width {
+            crossings[k] = match step(above[k - 1], row[k - 1], above[k]) {
+                Step::Mat if i == mid + 1 => (k - 1, k),
+                Step::Del if i == mid + 1 => (k, k),
+                Step::Mat | Step::Gap if k == 1 => (0, k),
+                Step::Mat => previous[k - 1],
+                Step::Gap => crossings[k - 1],
+                Step::Del => previous[k],
+            };
+        }
+    });
+    let (a, b) = crossings[width];
+    // The lower half from the b-th cell. Its left boundary is the column b-1.
+    let lower_rows = (mid + 1, rows.1);
+    let lower_left = &left[mid + 1 - rows.0..];
+    let mut boundary = Vec::with_capacity(rows.1 - mid);
+    if b > 1 {
+        let boundary_cols = (cols.0, cols.0 + b - 2);
+        forward(
+            x1,
+            x2,
+            dist,
+            lower_rows,
+            boundary_cols,
+            &mid_row[..b],
+            lower_left,
+            |_, _, row| {
+                boundary.push(row[b - 1]);
+            },
+        );
+    }
+    let lower_left = if b > 1 { &boundary } else { lower_left };
+    let lower_cols = (cols.0 + b - 1, cols.1);
+    solve(
+        x1,
+        x2,
+        dist,
+        lower_rows,
+        lower_cols,
+        &mid_row[b - 1..],
+        lower_left,
+        cells,
+    );
+    if a > 0 {
+        let upper_left = &left[..=mid - rows.0];
+        let upper_cols = (cols.0, cols.0 + a - 1);
+        solve(
+            x1,
+            x2,
+            dist,
+            (rows.0, mid),
+            upper_cols,
+            &top[..=a],
+            upper_left,
+            cells,
+        );
+    }
+}
+
+enum Step {
+    Mat,
+    Gap,
+    Del,
+}
+
+// The traceback of Mode::Full and Mode::Sub: ties are resolved in the order of
+// match, gap, and deletion.
+#[inline]
+fn step<T: Float>(mat: T, gap: T, del: T) -> Step {
+    if mat <= gap && mat <= del {
+        Step::Mat
+    } else if gap <= del {
+        Step::Gap
+    } else {
+        Step::Del
+    }
+}
+
+// Fill the region row by row with the same recurrence as Mode::Full and Mode::Sub,
+// and call visit(i, the row i-1, the row i) for each row i.
+// Both rows start with the score of the left boundary, as top and left of solve.
+#[allow(clippy::too_many_arguments)]
+fn forward<D, F, T, V>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    rows: (usize, usize),
+    cols: (usize, usize),
+    top: &[T],
+    left: &[T],
+    mut visit: V,
+) where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    V: FnMut(usize, &[T], &[T]),
+{
+    let width = cols.1 - cols.0 + 1;
+    let mut previous = top.to_vec();
+    let mut current = vec![T::infinity(); width + 1];
+    for i in rows.0..=rows.1 {
+        current[0] = left[i - rows.0];
+        for k in 1..=width {
+            let min = current[k - 1].min(previous[k].min(previous[k - 1]));
+            let cost = dist(&x1[i], &x2[cols.0 + k - 1]);
+            current[k] = if min.is_infinite() { min } else { min + cost };
+        }
+        visit(i, &previous, &current);
+        std::mem::swap(&mut previous, &mut current);
+    }
+}
+
+// Plain DP with traceback for a small region.
+#[allow(clippy::too_many_arguments)]
+fn traceback<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    rows: (usize, usize),
+    cols: (usize, usize),
+    top: &[T],
+    left: &[T],
+    cells: &mut Vec<(usize, usize)>,
+) where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    let mut dp = vec![top.to_vec()];
+    forward(x1, x2, dist, rows, cols, top, left, |_, _, row| {
+        dp.push(row.to_vec())
+    });
+    let (mut i, mut k) = (rows.1 - rows.0 + 1, cols.1 - cols.0 + 1);
+    while i > 0 && k > 0 {
+        cells.push((rows.0 + i - 1, cols.0 + k - 1));
+        match step(dp[i - 1][k - 1], dp[i][k - 1], dp[i - 1][k]) {
+            Step::Mat => {
+                i -= 1;
+                k -= 1;
+            }
+            Step::Gap => k -= 1,
+            Step::Del => i -= 1,
+        }
+    }
+}
+
+#[test]
+fn small_full() {
+    let x1 = vec![0, 1, 2, 3];
+    let x2 = vec![0, 1, 1, 2, 3, 3];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = linear_dtw(&x1, &x2, &d, false).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(
+        aln.path,
+        Some((vec![0, 1, 1, 2, 3, 3], vec![0, 1, 2, 3, 4, 5]))
+    );
+}
+
+#[test]
+fn small_sub() {
+    let x1 = vec![1, 2, 3];
+    let x2 = vec![0, 0, 1, 2, 2, 3, 0];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = linear_dtw(&x1, &x2, &d, true).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.reference_span, Some((2, 6)));
+}
+
+#[test]
+fn recursion_is_consistent() {
+    // A region large enough to be divided several times.
+    let x1: Vec<_> = (0..300).map(|i| (i * 7 % 13) as f32).collect();
+    let x2: Vec<_> = (0..200).map(|i| (i * 5 % 11) as f32).collect();
+    let d = |x: &f32, y: &f32| (x - y).abs();
+    for &is_sub in &[false, true] {
+        let aln = linear_dtw(&x1, &x2, &d, is_sub).unwrap();
+        let (xpath, ypath) = aln.path.unwrap();
+        assert_eq!(xpath[0], 0);
+        assert_eq!(xpath[xpath.len() - 1], 299);
+        let sum: f32 = xpath
+            .iter()
+            .zip(ypath.iter())
+            .map(|(&i, &j)| d(&x1[i], &x2[j]))
+            .sum();
+        assert_eq!(aln.score, sum);
+        for w in xpath
+            .iter()
+            .zip(ypath.iter())
+            .collect::<Vec<_>>()
+            .windows(2)
+        {
+            let (di, dj) = (w[1].0 - w[0].0, w[1].1 - w[0].1);
+            assert!(di <= 1 && dj <= 1 && di + dj > 0);
+        }
+    }
+}
@@ -4,51 +4,80 @@
 
 //!A tiny implementation of dynamic time warping for Rust language.
 //!
+extern crate memmap2;
 extern crate num;
 extern crate order_stat;
 extern crate rand;
 mod dtw_chiba;
 mod dtw_itakura;
+mod error;
 mod fastdtw;
+mod hirschberg;
+pub mod lower_bounds;
+mod multidim;
 mod normalize;
 mod nw;
+mod pruned;
 mod quickdtw;
 mod result;
 mod scoutingdtw;
+mod spring;
+mod step_pattern;
 mod thresholddtw;
+mod transform;
 mod ucr_dtw;
 /// module for utility such as optimal dynamic time warping.
 /// also some other convinient functions are here.
 pub mod utils;
-pub use fastdtw::fast_dtw;
+pub use error::{DtwError, IndexError};
+pub use fastdtw::{
+    fast_dtw, fast_dtw_with_merge, fast_dtw_with_options, fast_dtw_with_step, Coarsen,
+    FastDtwOptions,
+};
+pub use hirschberg::linear_dtw;
+pub use multidim::{dtw_dependent, dtw_independent, squared_euclidean, IndependentAlignment};
 pub use normalize::histgram_equalization;
 pub use normalize::histgram_modify;
 pub use normalize::normalize;
 pub use normalize::normalize_mut;
 pub use normalize::NormalizeType;
 use num::Float;
-use num::Num;
 pub use nw::needleman_dtw;
-pub use scoutingdtw::{scouting_dtw, scouting_threshold_dtw};
+pub use pruned::{ea_pruned_dtw, pruned_dtw};
+pub use quickdtw::subdtw_top_k;
+pub use result::{Alignment, Normalization};
+pub use scoutingdtw::{scouting_dtw, scouting_threshold_dtw, scouting_threshold_dtw_with_path};
+pub use spring::SpringMatcher;
 use std::vec::Vec;
-pub use thresholddtw::thresholddtw;
-pub use ucr_dtw::DynamicTimeWarping;
-/// Return type of DTW functions.
+pub use step_pattern::StepPattern;
+pub use thresholddtw::{thresholddtw, thresholddtw_normalized};
+pub use transform::{derivative, dtw_transformed, Transform};
+pub use ucr_dtw::{DynamicTimeWarping, IndexElement, PruningStats};
+/// Warping path: (query indices, reference indices).
 pub type TRACE = (Vec<usize>, Vec<usize>);
 /// Mode specifier to be used by other function
 /// to decide the dynamic time warping mode.
 /// SakoeChiba(usize): Using Sakoe-Chiba band with the specified band width.
-/// Itakura(usize): Using Itakura pentagram with the specified max band width.
+/// SubSakoeChiba(usize): Searching the best window of the reference with Sakoe-Chiba band.
+/// Itakura(usize): Using Itakura parallelogram with the specified max band width.
 #[derive(Debug, Copy, Clone)]
 pub enum Mode {
-    /// Specifier for Sakoe-Chiba band.
+    /// Specifier for Sakoe-Chiba band around the rescaled diagonal.
+    /// The two series may have different lengths.
     SakoeChiba(usize),
-    /// Specifier for Itakuta's band.
+    /// Subsequence search with Sakoe-Chiba band. The best window of the reference
+    /// with the same length as the query is reported with its path.
+    SubSakoeChiba(usize),
+    /// Specifier for Itakura's parallelogram. The two series may have different lengths.
     Itakura(usize),
     /// Specifier for sub dtw.
     Sub,
     /// Specifier for full dtw.
     Full,
+    /// Full dtw with the path recovered without the whole DP table.
+    LinearFull,
+    /// Sub dtw with the path recovered without the whole DP table.
+    LinearSub,
     /// Specifier for fast dtw
     Fast(usize),
     /// Specifier for fast sub dtw
@@ -65,9 +94,12 @@ impl std::fmt::Display for Mode {
             Mode::FastSub(b) => format!("FastSub,{}", b),
             Mode::Full => "Full".to_string(),
             Mode::Itakura(b) => format!("Itakura{}", b),
+            Mode::LinearFull => "LinearFull".to_string(),
+            Mode::LinearSub => "LinearSub".to_string(),
             Mode::QuickSub => "QuickSub".to_string(),
             Mode::SakoeChiba(b) => format!("SakoeChiba({})", b),
             Mode::Sub => "Sub".to_string(),
+            Mode::SubSakoeChiba(b) => format!("SubSakoeChiba({})", b),
             Mode::Scouting(num_scouts, num_packs) => {
                 format!("Scouting({}_{})", num_scouts, num_packs)
             }
@@ -79,33 +111,131 @@ impl std::fmt::Display for Mode {
 /// A generic function of dymanic time warping.
 /// This function can execute dynamic time warping
 /// for almost all data type with appropriate function dist.
-/// (score,(path,path),index to start the alignment)
+/// Whether the path and the location in the reference are computed
+/// depends on the mode. See `Alignment`.
 /// X1 IS QUERY AND X2 IS REFERENCE NOT VISE VERSA
-pub fn dtw<D, F, T>(x1: &[D], x2: &[D], mode: Mode, dist: &F) -> Result<(T, TRACE, usize), String>
+pub fn dtw<D, F, T>(x1: &[D], x2: &[D], mode: Mode, dist: &F) -> Result<Alignment<T>, DtwError>
 where
     F: Fn(&D, &D) -> T,
     T: Float,
-    D: Num + Copy,
+    D: Coarsen,
 {
-    let err = format!(
-        "invalid input: the lengths are different,while restricted mode was chosen:{},{}",
-        x1.len(),
-        x2.len()
-    );
-    match mode {
-        Mode::SakoeChiba(_) if x1.len() != x2.len() => Err(err),
-        Mode::SakoeChiba(n) => dtw_chiba::dtw_chiba(x1, x2, dist, n),
-        Mode::Itakura(_) if x1.len() != x2.len() => Err(err),
-        Mode::Itakura(n) => dtw_itakura::dtw_itakura(x1, x2, dist, n),
-        Mode::Full => dtw_norestrict(x1, x2, dist, mode),
-        Mode::Sub => dtw_norestrict(x1, x2, dist, mode),
-        Mode::Fast(radius) => fastdtw::fast_dtw(x1, x2, dist, radius, false),
-        Mode::FastSub(radius) => fastdtw::fast_dtw(x1, x2, dist, radius, true),
-        Mode::QuickSub => quickdtw::quickdtw(x1, x2, dist),
-        Mode::Scouting(num_scouts, num_packs) => {
-            scoutingdtw::scouting_dtw(x1, x2, dist, Some(num_scouts), Some(num_packs))
+    dtw_with_step(x1, x2, mode, StepPattern::Symmetric1, dist)
+}
+
+/// Dynamic time warping with the specified step pattern.
+/// Patterns other than StepPattern::Symmetric1 are supported by
+/// Full, Sub, Fast, and FastSub modes. Otherwise,
+/// it returns Err(DtwError::UnsupportedStepPattern).
+/// The returned score is not normalized. See `Alignment::normalized_score`.
+pub fn dtw_with_step<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    mode: Mode,
+    step: StepPattern,
+    dist: &F,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    D: Coarsen,
+{
+    dtw_by(x1, x2, mode, step, dist, &fastdtw::reduce_by, T::infinity())
+}
+
+/// Dynamic time warping abandoned once the score is known to exceed cutoff,
+/// so that a nearest neighbor search can pass the best score so far to any mode.
+/// It returns Err(DtwError::Abandoned{row,..}) unless the score is at most cutoff,
+/// where row is the (1-based) query position where the computation stopped.
+/// Full, Sub, SakoeChiba, SubSakoeChiba, Itakura, Fast, FastSub, and QuickSub modes
+/// stop as soon as every cell of a row exceeds cutoff, thus dist should be nonnegative.
+/// The other modes check the score at the end and report the last row.
+/// ```rust
+/// use dtw::{dtw, dtw_with_cutoff, DtwError, Mode};
+/// let x1 = vec![0., 1., 2., 3.];
+/// let x2 = vec![5., 6., 7., 8.];
+/// let dist = |x: &f64, y: &f64| (x - y).abs();
+/// let aln = dtw_with_cutoff(&x1, &x1, Mode::Full, &dist, 1.).unwrap();
+/// assert_eq!(aln, dtw(&x1, &x1, Mode::Full, &dist).unwrap());
+/// let err = dtw_with_cutoff(&x1, &x2, Mode::Full, &dist, 1.).unwrap_err();
+/// assert_eq!(err, DtwError::Abandoned { row: 1, total: 4 });
+/// ```
+pub fn dtw_with_cutoff<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    mode: Mode,
+    dist: &F,
+    cutoff: T,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    D: Coarsen,
+{
+    let step = StepPattern::Symmetric1;
+    dtw_by(x1, x2, mode, step, dist, &fastdtw::reduce_by, cutoff)
+}
+
+// Dispatch to each mode. reduce makes the coarser series for FastDTW.
+// The alignment is returned only if its score is at most cutoff.
+pub(crate) fn dtw_by<D, F, T, R>(
+    x1: &[D],
+    x2: &[D],
+    mode: Mode,
+    step: StepPattern,
+    dist: &F,
+    reduce: &R,
+    cutoff: T,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    R: Fn(&[D], usize) -> Vec<D>,
+{
+    let aln = if step != StepPattern::Symmetric1 {
+        match mode {
+            Mode::Full => step_pattern::pattern_dtw(x1, x2, dist, step, false),
+            Mode::Sub => step_pattern::pattern_dtw(x1, x2, dist, step, true),
+            Mode::Fast(radius) => {
+                let options = FastDtwOptions::new(radius).step(step);
+                fastdtw::fast_dtw_by(x1, x2, dist, false, &options, reduce, cutoff)
+            }
+            Mode::FastSub(radius) => {
+                let options = FastDtwOptions::new(radius).step(step);
+                fastdtw::fast_dtw_by(x1, x2, dist, true, &options, reduce, cutoff)
+            }
+            _ => Err(DtwError::UnsupportedStepPattern),
+        }
+    } else {
+        match mode {
+            Mode::SakoeChiba(n) => dtw_chiba::dtw_chiba(x1, x2, dist, n, cutoff),
+            Mode::SubSakoeChiba(n) => dtw_chiba::sub_dtw_chiba(x1, x2, dist, n, cutoff),
+            Mode::Itakura(n) => dtw_itakura::dtw_itakura(x1, x2, dist, n, cutoff),
+            Mode::Full => dtw_norestrict(x1, x2, dist, mode, cutoff),
+            Mode::Sub => dtw_norestrict(x1, x2, dist, mode, cutoff),
+            Mode::LinearFull => hirschberg::linear_dtw(x1, x2, dist, false),
+            Mode::LinearSub => hirschberg::linear_dtw(x1, x2, dist, true),
+            Mode::Fast(radius) => {
+                let options = FastDtwOptions::new(radius);
+                fastdtw::fast_dtw_by(x1, x2, dist, false, &options, reduce, cutoff)
+            }
+            Mode::FastSub(radius) => {
+                let options = FastDtwOptions::new(radius);
+                fastdtw::fast_dtw_by(x1, x2, dist, true, &options, reduce, cutoff)
+            }
+            Mode::QuickSub => quickdtw::quickdtw(x1, x2, dist, cutoff),
+            Mode::Scouting(num_scouts, num_packs) => {
+                scoutingdtw::scouting_dtw(x1, x2, dist, Some(num_scouts), Some(num_packs))
+            }
         }
+    }?;
+    if aln.score > cutoff {
+        return Err(DtwError::Abandoned {
+            row: x1.len(),
+            total: x1.len(),
+        });
     }
+    Ok(aln)
 }
 
 fn dtw_norestrict<D, F, T>(
@@ -113,7 +243,8 @@ fn dtw_norestrict<D, F, T>(
     x2: &[D],
     dist: &F,
     mode: Mode,
-) -> Result<(T, TRACE, usize), String>
+    cutoff: T,
+) -> Result<Alignment<T>, DtwError>
 where
     F: Fn(&D, &D) -> T,
     T: Float,
@@ -122,7 +253,7 @@ where
     let x1len = x1.len();
     let x2len = x2.len();
     if x1len == 0 || x2len == 0 {
-        return Err("the length of given time series may be empty".to_string());
+        return Err(DtwError::EmptyInput);
     }
     let mut res = match mode {
         Mode::Full => vec![vec![T::infinity(); x2len + 1]; x1len + 1],
@@ -142,6 +273,12 @@ where
             let min: T = res[i][j - 1].min(res[i - 1][j].min(res[i - 1][j - 1]));
             res[i][j] = if !min.is_infinite() { min + cost } else { min };
         }
+        if res[i][1..].iter().all(|&score| score > cutoff) {
+            return Err(DtwError::Abandoned {
+                row: i,
+                total: x1len,
+            });
+        }
     }
     let mut x1path = vec![];
     let mut x2path = vec![];
@@ -158,38 +295,27 @@ where
     while x1pos > 0 {
         x1path.push(x1pos - 1);
         x2path.push(x2pos - 1);
-        let next = nextpos(x1, x2, &res, x1pos, x2pos, dist);
+        let next = nextpos(&res, x1pos, x2pos);
         x1pos = next.0;
         x2pos = next.1;
     }
     x1path.reverse();
     x2path.reverse();
-    match mode {
-        Mode::Full => Ok((score, (x1path, x2path), 0)),
-        Mode::Sub => Ok((score, (x1path, x2path), x2pos - 1)),
-        _ => unreachable!("could not happen"),
-    }
+    Ok(Alignment::from_path(score, (x1path, x2path)))
 }
 
-fn nextpos<D, F, T>(
-    x1: &[D],
-    x2: &[D],
-    res: &Vec<Vec<T>>,
-    x1pos: usize,
-    x2pos: usize,
-    dist: &F,
-) -> (usize, usize)
+// Ties are resolved in the order of match, gap, and deletion,
+// so that the chosen predecessor is always an optimal one.
+fn nextpos<T>(res: &[Vec<T>], x1pos: usize, x2pos: usize) -> (usize, usize)
 where
-    F: Fn(&D, &D) -> T,
     T: Float,
 {
-    let current_score = res[x1pos][x2pos] - dist(&x1[x1pos - 1], &x2[x2pos - 1]);
-    let match_path = res[x1pos - 1][x2pos - 1] - current_score;
-    let gap_path = res[x1pos][x2pos - 1] - current_score;
-    let del_path = res[x1pos - 1][x2pos] - current_score;
-    if match_path < gap_path && match_path < del_path {
+    let match_path = res[x1pos - 1][x2pos - 1];
+    let gap_path = res[x1pos][x2pos - 1];
+    let del_path = res[x1pos - 1][x2pos];
+    if match_path <= gap_path && match_path <= del_path {
         (x1pos - 1, x2pos - 1)
-    } else if gap_path < match_path && gap_path < del_path {
+    } else if gap_path <= del_path {
         (x1pos, x2pos - 1)
     } else {
         (x1pos - 1, x2pos)
@@ -208,7 +334,7 @@ where
 /// Currently no "window" mode is implemented.
 pub fn dtw_with_path(x1: &[f32], x2: &[f32]) -> (f32, (Vec<usize>, Vec<usize>)) {
     let d = |x: &f32, y: &f32| (x - y).powi(2);
-    let (score, (x1path, x2path), _) = dtw(x1, x2, Mode::Full, &d).unwrap();
+    let aln = dtw(x1, x2, Mode::Full, &d).unwrap();
     // res[0][0] = 0.0;
     // for i in 1..x1len+1{
     //     for j in 1..x2len+1{
@@ -217,7 +343,7 @@ pub fn dtw_with_path(x1: &[f32], x2: &[f32]) -> (f32, (Vec<usize>, Vec<usize>))
     //     }
     // }
     //    (res[x1len][x2len],res)
-    (score, (x1path, x2path))
+    (aln.score, aln.path.unwrap())
 }
 
 /// Dynamic time warping for 1 dimentional data.
@@ -229,32 +355,32 @@ pub fn normal_dtw(x1: &[f32], x2: &[f32]) -> f32 {
     dtw_with_path(x1, x2).0
 }
 
-// ///Dynamic time warping for 2 dimentional data.
-// ///
-// ///A naive implementation for dynamic time warping, thus
-// ///the time complexity is O(mn),where m and n are the lengths of the data respectively.
-// ///If you only want to compute the dtw score, use dtw(x1,x2).
-// pub fn dtw2_with_path(x1:&[[f32;2]],x2:&[[f32;2]]) -> (f32,(Vec<usize>,Vec<usize>)){
-//     let d = |x:&[f32;2],y:&[f32;2]| ((x[0]-y[0]).powi(2) + (x[1]-y[1]).powi(2)).sqrt();
-//     let (score,(x1path,x2path),_) = dtw(x1,x2,Mode::Full,&d).unwrap();
-//     (score,(x1path,x2path))
-// }
+///Dynamic time warping for 2 dimentional data.
+///
+///A naive implementation for dynamic time warping, thus
+///the time complexity is O(mn),where m and n are the lengths of the data respectively.
+///If you only want to compute the dtw score, use dtw2(x1,x2).
+pub fn dtw2_with_path(x1: &[[f32; 2]], x2: &[[f32; 2]]) -> (f32, (Vec<usize>, Vec<usize>)) {
+    let d = |x: &[f32], y: &[f32]| squared_euclidean(x, y).sqrt();
+    let aln = dtw_dependent(x1, x2, Mode::Full, &d).unwrap();
+    (aln.score, aln.path.unwrap())
+}
 
-// /// Dynamic time warping for 2 dimentional data.
-// ///
-// /// This is a wrapper function of dtw2_with_path.
-// /// Almost the same as dtw().
-// ///
-// pub fn dtw2(x1:&[[f32;2]],x2:&[[f32;2]]) -> f32{
-//     dtw2_with_path(x1,x2).0
-// }
+/// Dynamic time warping for 2 dimentional data.
+///
+/// This is a wrapper function of dtw2_with_path.
+/// Almost the same as dtw().
+///
+pub fn dtw2(x1: &[[f32; 2]], x2: &[[f32; 2]]) -> f32 {
+    dtw2_with_path(x1, x2).0
+}
 
 /// Subsequence dynamic time warping for 1 dimentional data.
 /// x1 is query,x2 is reference
 pub fn subdtw_with_path(x1: &[f32], x2: &[f32]) -> (f32, (Vec<usize>, Vec<usize>)) {
     let d = |x: &f32, y: &f32| (x - y).powi(2);
-    let (score, (x1path, x2path), _) = dtw(x1, x2, Mode::Sub, &d).unwrap();
-    (score, (x1path, x2path))
+    let aln = dtw(x1, x2, Mode::Sub, &d).unwrap();
+    (aln.score, aln.path.unwrap())
     // let x1len = x1.len();
     // let x2len = x2.len();
     // let mut res = vec![vec![BIGNUM;x2len+1];x1len+1];
@@ -303,8 +429,7 @@ pub fn subdtw(x1: &[f32], x2: &[f32]) -> f32 {
 pub fn sub_warppath(x1: &[f32], x2: &[f32], _score: &Vec<Vec<f32>>) -> (Vec<usize>, Vec<usize>) {
     // find the starting position
     let d = |x: &f32, y: &f32| (x - y).powi(2);
-    let (_, (x1path, x2path), _) = dtw(x1, x2, Mode::Full, &d).unwrap();
-    (x1path, x2path)
+    dtw(x1, x2, Mode::Full, &d).unwrap().path.unwrap()
     //     let mut query_pos = x1.len();
     // let mut ref_pos = score[x1.len()].iter()
     //     .enumerate()
@@ -355,8 +480,8 @@ where
 /// as other method such as subdtw().
 pub fn subdtw_map(x1: &[f32], x2: &[f32]) -> (f32, usize) {
     let d = |x: &f32, y: &f32| (x - y).powi(2);
-    let (score, _, location) = dtw(x1, x2, Mode::Sub, &d).unwrap();
-    (score, location)
+    let aln = dtw(x1, x2, Mode::Sub, &d).unwrap();
+    (aln.score, aln.reference_start().unwrap())
     //     let x1len = x1.len();
     //     let x2len = x2.len();
     // //    let mut res = vec![vec![BIGNUM;x2len+1];x1len+1];
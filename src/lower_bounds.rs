@@ -0,0 +1,254 @@
+//! Lower bounds of banded dynamic time warping between two series of the same length.
+//!
+//! radius is the band of the warping: a path can use (i,j) only if |i-j| <= radius,
+//! which is `Mode::SakoeChiba(2 * radius + 1)` for series of the same length and radius > 0.
+//! Each bound is not larger than the banded dynamic time warping
+//! when dist(x,y) is a nondecreasing function of |x-y| which is zero at 0
+//! and superadditive, such as |x-y| and (x-y)^2.
+//! LB_Kim and LB_Keogh only need dist to be nonnegative and monotone.
+//!
+//! The envelope of a series is the minimum and the maximum of the window of each position,
+//! and LB_Keogh sums the distance from each sample of a series to the envelope of the other.
+//! ```rust
+//! use dtw::lower_bounds::{envelope, lb_keogh, lb_webb};
+//! use dtw::{dtw, Mode};
+//! let x: Vec<f64> = (0..30).map(|i| (i as f64 / 3.).sin()).collect();
+//! let y: Vec<f64> = (0..30).map(|i| (i as f64 / 4.).cos()).collect();
+//! let dist = |a: &f64, b: &f64| (a - b).powi(2);
+//! let (lower, upper) = envelope(&y, 2);
+//! let score = dtw(&x, &y, Mode::SakoeChiba(5), &dist).unwrap().score;
+//! assert!(lb_keogh(&x, &lower, &upper, &dist) <= score);
+//! assert!(lb_webb(&x, &y, 2, &dist) <= score);
+//! ```
+use num::Float;
+use std::collections::VecDeque;
+
+/// The lower and the upper envelope of xs.
+/// The envelope at i covers xs[i-radius..=i+radius].
+/// It takes O(n) time regardless of the radius by the streaming minimum and maximum
+/// of Lemire (2006): the candidates of the minimum (maximum) of the window are kept
+/// in a deque in increasing (decreasing) order of the values.
+pub fn envelope<D>(xs: &[D], radius: usize) -> (Vec<D>, Vec<D>)
+where
+    D: Copy + PartialOrd,
+{
+    let len = xs.len();
+    let radius = radius.min(len);
+    let mut lower = Vec::with_capacity(len);
+    let mut upper = Vec::with_capacity(len);
+    let mut minima: VecDeque<usize> = VecDeque::with_capacity(2 * radius + 1);
+    let mut maxima: VecDeque<usize> = VecDeque::with_capacity(2 * radius + 1);
+    for k in 0..len + radius {
+        if k < len {
+            while minima.back().is_some_and(|&j| xs[k] <= xs[j]) {
+                minima.pop_back();
+            }
+            minima.push_back(k);
+            while maxima.back().is_some_and(|&j| xs[j] <= xs[k]) {
+                maxima.pop_back();
+            }
+            maxima.push_back(k);
+        }
+        if k < radius {
+            continue;
+        }
+        // The window of i = k - radius is complete.
+        let start = (k - radius).saturating_sub(radius);
+        while minima.front().is_some_and(|&j| j < start) {
+            minima.pop_front();
+        }
+        while maxima.front().is_some_and(|&j| j < start) {
+            maxima.pop_front();
+        }
+        lower.push(xs[minima[0]]);
+        upper.push(xs[maxima[0]]);
+    }
+    (lower, upper)
+}
+
+/// LB_Kim (LB_KimFL of the UCR suite): the first and the last cells,
+/// and the nearest cells to them, which every path passes.
+pub fn lb_kim<D, T, F>(x: &[D], y: &[D], dist: &F) -> T
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    check_length(x, y);
+    let len = x.len();
+    match len {
+        0 => T::zero(),
+        1 => dist(&x[0], &y[0]),
+        2 | 3 => dist(&x[0], &y[0]) + dist(&x[len - 1], &y[len - 1]),
+        _ => {
+            let second = |(a0, a1): (&D, &D), (b0, b1): (&D, &D)| {
+                dist(a1, b0).min(dist(a0, b1)).min(dist(a1, b1))
+            };
+            dist(&x[0], &y[0])
+                + dist(&x[len - 1], &y[len - 1])
+                + second((&x[0], &x[1]), (&y[0], &y[1]))
+                + second((&x[len - 1], &x[len - 2]), (&y[len - 1], &y[len - 2]))
+        }
+    }
+}
+
+/// LB_Keogh of x to the envelope (lower, upper) of the other series.
+pub fn lb_keogh<D, T, F>(x: &[D], lower: &[D], upper: &[D], dist: &F) -> T
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    D: PartialOrd,
+{
+    check_length(x, lower);
+    check_length(x, upper);
+    x.iter()
+        .zip(lower.iter().zip(upper.iter()))
+        .fold(T::zero(), |acc, (x, (l, u))| acc + outside(x, l, u, dist))
+}
+
+/// LB_Improved (Lemire, 2009): LB_Keogh of x to the envelope of y,
+/// plus LB_Keogh of y to the envelope of the projection of x onto the envelope of y.
+pub fn lb_improved<D, T, F>(x: &[D], y: &[D], radius: usize, dist: &F) -> T
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    D: Copy + PartialOrd,
+{
+    check_length(x, y);
+    let (lower, upper) = envelope(y, radius);
+    let projection: Vec<D> = x
+        .iter()
+        .zip(lower.iter().zip(upper.iter()))
+        .map(|(&x, (&l, &u))| clamp(x, l, u))
+        .collect();
+    let (p_lower, p_upper) = envelope(&projection, radius);
+    lb_keogh(x, &lower, &upper, dist) + lb_keogh(y, &p_lower, &p_upper, dist)
+}
+
+/// LB_Enhanced (Tan et al., 2019) with v bands.
+/// The i-th band from the start is the cells (i,j) and (j,i) with j <= i in the band of the warping,
+/// and every path passes it. So is the i-th band from the end.
+/// The minimum cost of the first v bands and the last v bands are added to
+/// LB_Keogh of the rest of x. v is at most half of the length.
+pub fn lb_enhanced<D, T, F>(x: &[D], y: &[D], radius: usize, v: usize, dist: &F) -> T
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    D: Copy + PartialOrd,
+{
+    check_length(x, y);
+    let len = x.len();
+    if len == 0 {
+        return T::zero();
+    }
+    if len == 1 {
+        return dist(&x[0], &y[0]);
+    }
+    let v = v.max(1).min(len / 2);
+    let band = |i: usize, cell: &dyn Fn(usize, usize) -> T| {
+        (i.saturating_sub(radius)..i + 1)
+            .fold(T::infinity(), |acc, j| acc.min(cell(i, j)).min(cell(j, i)))
+    };
+    let from_start = |i: usize, j: usize| dist(&x[i], &y[j]);
+    let from_end = |i: usize, j: usize| dist(&x[len - 1 - i], &y[len - 1 - j]);
+    let bands = (0..v).fold(T::zero(), |acc, i| {
+        acc + band(i, &from_start) + band(i, &from_end)
+    });
+    let (lower, upper) = envelope(y, radius);
+    let rest = (v..len - v).fold(T::zero(), |acc, i| {
+        acc + outside(&x[i], &lower[i], &upper[i], dist)
+    });
+    bands + rest
+}
+
+/// LB_Webb (Webb and Petitjean, 2021): LB_Keogh of x to the envelope (L,U) of y,
+/// plus the distance from y[j] to the envelope (L',U') of x where it does not share
+/// a cell with the first term: y[j] > U'[j] counts if the upper envelope of L is at most U'[j],
+/// and y[j] < L'[j] counts if the lower envelope of U is at least L'[j].
+pub fn lb_webb<D, T, F>(x: &[D], y: &[D], radius: usize, dist: &F) -> T
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    D: Copy + PartialOrd,
+{
+    check_length(x, y);
+    let (lower, upper) = envelope(y, radius);
+    let (x_lower, x_upper) = envelope(x, radius);
+    let (_, upper_of_lower) = envelope(&lower, radius);
+    let (lower_of_upper, _) = envelope(&upper, radius);
+    let mut lb = lb_keogh(x, &lower, &upper, dist);
+    for j in 0..y.len() {
+        if y[j] > x_upper[j] && upper_of_lower[j] <= x_upper[j] {
+            lb = lb + dist(&y[j], &x_upper[j]);
+        } else if y[j] < x_lower[j] && lower_of_upper[j] >= x_lower[j] {
+            lb = lb + dist(&y[j], &x_lower[j]);
+        }
+    }
+    lb
+}
+
+// The distance from x to the range [lower, upper].
+#[inline]
+fn outside<D, T, F>(x: &D, lower: &D, upper: &D, dist: &F) -> T
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    D: PartialOrd,
+{
+    if x < lower {
+        dist(x, lower)
+    } else if x > upper {
+        dist(x, upper)
+    } else {
+        T::zero()
+    }
+}
+
+#[inline]
+fn clamp<D: PartialOrd>(x: D, lower: D, upper: D) -> D {
+    if x < lower {
+        lower
+    } else if x > upper {
+        upper
+    } else {
+        x
+    }
+}
+
+fn check_length<D, E>(x: &[D], y: &[E]) {
+    assert_eq!(
+        x.len(),
+        y.len(),
+        "the lower bounds need series of the same length"
+    );
+}
+
+#[test]
+fn envelope_test() {
+    let xs = vec![3, 1, 4, 1, 5, 9, 2];
+    let (lower, upper) = envelope(&xs, 1);
+    assert_eq!(lower, vec![1, 1, 1, 1, 1, 2, 2]);
+    assert_eq!(upper, vec![3, 4, 4, 5, 9, 9, 9]);
+    assert_eq!(envelope(&xs, 0), (xs.clone(), xs.clone()));
+    assert_eq!(envelope::<i32>(&[], 3), (vec![], vec![]));
+}
+
+#[test]
+fn bounds_on_diagonal() {
+    // With radius 0, the only path is the diagonal, and LB_Keogh is exact.
+    let x = vec![0., 2., 1., 5.];
+    let y = vec![1., 1., 3., 2.];
+    let dist = |a: &f64, b: &f64| (a - b).abs();
+    let (lower, upper) = envelope(&y, 0);
+    assert_eq!(lb_keogh(&x, &lower, &upper, &dist), 7.);
+    assert_eq!(lb_improved(&x, &y, 0, &dist), 7.);
+    assert_eq!(lb_webb(&x, &y, 0, &dist), 7.);
+    assert_eq!(lb_enhanced(&x, &y, 0, 2, &dist), 7.);
+    assert_eq!(lb_kim(&x, &y, &dist), 6.);
+}
+
+#[test]
+#[should_panic]
+fn different_length() {
+    let dist = |a: &f64, b: &f64| (a - b).abs();
+    lb_kim(&[0.], &[1., 2.], &dist);
+}
@@ -0,0 +1,171 @@
+use super::dtw;
+use super::dtw_by;
+use super::Alignment;
+use super::DtwError;
+use super::Mode;
+use super::StepPattern;
+use num::Float;
+
+/// Squared Euclidean distance between two multidimensional samples.
+pub fn squared_euclidean<T: Float>(x: &[T], y: &[T]) -> T {
+    x.iter()
+        .zip(y.iter())
+        .fold(T::zero(), |acc, (&a, &b)| acc + (a - b) * (a - b))
+}
+
+/// Result of independent dynamic time warping (DTW-I).
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndependentAlignment<T> {
+    /// The sum of the scores of all channels.
+    pub score: T,
+    /// The alignment of each channel.
+    pub channels: Vec<Alignment<T>>,
+}
+
+/// Dependent dynamic time warping (DTW-D) for multidimensional time series,
+/// such as `&[[f32; 3]]` or `&[Vec<f32>]`.
+/// All channels share one warping path, and dist compares two samples as a whole
+/// (e.g. squared_euclidean). Every mode is available, FastDTW averages the channels
+/// of two adjacent samples to make the coarser series.
+/// The samples are passed to dist as they are, and only FastDTW copies them.
+/// X1 IS QUERY AND X2 IS REFERENCE.
+pub fn dtw_dependent<V, F, T>(
+    x1: &[V],
+    x2: &[V],
+    mode: Mode,
+    dist: &F,
+) -> Result<Alignment<T>, DtwError>
+where
+    V: AsRef<[T]>,
+    F: Fn(&[T], &[T]) -> T,
+    T: Float,
+{
+    let dim = dimension(x1, x2)?;
+    check_rows(x1, dim)?;
+    check_rows(x2, dim)?;
+    match mode {
+        // FastDTW averages adjacent samples, so the samples are copied into rows.
+        Mode::Fast(_) | Mode::FastSub(_) => {
+            let (x1, x2) = (to_rows(x1), to_rows(x2));
+            let dist = |x: &Vec<T>, y: &Vec<T>| dist(x, y);
+            let step = StepPattern::Symmetric1;
+            dtw_by(&x1, &x2, mode, step, &dist, &reduce_rows, T::infinity())
+        }
+        _ => {
+            let dist = |x: &V, y: &V| dist(x.as_ref(), y.as_ref());
+            // The other modes never coarsen the series.
+            let reduce = |_: &[V], _: usize| -> Vec<V> { unreachable!() };
+            let step = StepPattern::Symmetric1;
+            dtw_by(x1, x2, mode, step, &dist, &reduce, T::infinity())
+        }
+    }
+}
+
+/// Independent dynamic time warping (DTW-I) for multidimensional time series.
+/// Each channel is warped on its own with dist, and the score is the sum of them.
+/// X1 IS QUERY AND X2 IS REFERENCE.
+pub fn dtw_independent<V, F, T>(
+    x1: &[V],
+    x2: &[V],
+    mode: Mode,
+    dist: &F,
+) -> Result<IndependentAlignment<T>, DtwError>
+where
+    V: AsRef<[T]>,
+    F: Fn(&T, &T) -> T,
+    T: Float,
+{
+    let dim = dimension(x1, x2)?;
+    check_rows(x1, dim)?;
+    check_rows(x2, dim)?;
+    let channel = |xs: &[V], c: usize| -> Vec<T> { xs.iter().map(|x| x.as_ref()[c]).collect() };
+    let channels = (0..dim)
+        .map(|c| dtw(&channel(x1, c), &channel(x2, c), mode, dist))
+        .collect::<Result<Vec<_>, _>>()?;
+    let score = channels.iter().fold(T::zero(), |acc, aln| acc + aln.score);
+    Ok(IndependentAlignment { score, channels })
+}
+
+// The number of channels. It should be the same among all samples.
+fn dimension<V, T>(x1: &[V], x2: &[V]) -> Result<usize, DtwError>
+where
+    V: AsRef<[T]>,
+{
+    match x1.first() {
+        Some(x) if !x2.is_empty() && !x.as_ref().is_empty() => Ok(x.as_ref().len()),
+        _ => Err(DtwError::EmptyInput),
+    }
+}
+
+// Every sample should have dim channels.
+fn check_rows<V, T>(xs: &[V], dim: usize) -> Result<(), DtwError>
+where
+    V: AsRef<[T]>,
+{
+    match xs.iter().map(|x| x.as_ref().len()).find(|&len| len != dim) {
+        Some(found) => Err(DtwError::DimensionMismatch {
+            expected: dim,
+            found,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn to_rows<V, T>(xs: &[V]) -> Vec<Vec<T>>
+where
+    V: AsRef<[T]>,
+    T: Float,
+{
+    xs.iter().map(|x| x.as_ref().to_vec()).collect()
+}
+
+fn reduce_rows<T: Float>(xs: &[Vec<T>], factor: usize) -> Vec<Vec<T>> {
+    xs.chunks(factor)
+        .map(|chunk| {
+            let count = T::from(chunk.len()).unwrap();
+            (0..chunk[0].len())
+                .map(|d| chunk.iter().fold(T::zero(), |acc, row| acc + row[d]) / count)
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn dependent_test() {
+    let x1 = vec![[0., 0.], [1., 1.], [2., 2.]];
+    let x2 = vec![[0., 0.], [1., 1.], [1., 1.], [2., 2.]];
+    let aln = dtw_dependent(&x1, &x2, Mode::Full, &squared_euclidean).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.path, Some((vec![0, 1, 1, 2], vec![0, 1, 2, 3])));
+}
+
+#[test]
+fn independent_test() {
+    // The two channels are shifted in opposite directions.
+    let x1 = vec![vec![0., 0.], vec![1., 0.], vec![0., 1.], vec![0., 0.]];
+    let x2 = vec![vec![0., 0.], vec![0., 1.], vec![1., 0.], vec![0., 0.]];
+    let d = |x: &f64, y: &f64| (x - y).powi(2);
+    let aln = dtw_independent(&x1, &x2, Mode::Full, &d).unwrap();
+    assert_eq!(aln.channels.len(), 2);
+    assert_eq!(aln.score, 0.);
+    let dependent = dtw_dependent(&x1, &x2, Mode::Full, &squared_euclidean).unwrap();
+    assert!(dependent.score > 0.);
+}
+
+#[test]
+fn dimension_mismatch() {
+    let x1 = vec![vec![0., 1.], vec![1.]];
+    let x2 = vec![vec![0., 1.]];
+    assert_eq!(
+        dtw_dependent(&x1, &x2, Mode::Full, &squared_euclidean).err(),
+        Some(DtwError::DimensionMismatch {
+            expected: 2,
+            found: 1
+        })
+    );
+    let empty: Vec<Vec<f64>> = vec![];
+    assert_eq!(
+        dtw_dependent(&empty, &x2, Mode::Full, &squared_euclidean).err(),
+        Some(DtwError::EmptyInput)
+    );
+}
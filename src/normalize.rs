@@ -30,7 +30,7 @@ fn z_normalize(xs: &[f32]) -> Vec<f32> {
 }
 #[test]
 fn test() {
-    let v = (0..100).map(|e| f32::from((e - 50 as i8))).collect();
+    let v: Vec<f32> = (0..100).map(|e| f32::from((e - 50 as i8))).collect();
     z_normalize(&v);
     assert!(true);
 }
@@ -1,15 +1,17 @@
+use super::Alignment;
+use super::DtwError;
 use num::Float;
 
 /// NW algorithm interpreted by dtw.
 pub fn needleman_dtw<D,F,T>(x1:&[D],x2:&[D],dist:&F,gap:T) 
-                   -> Result<(T,(Vec<usize>,Vec<usize>),usize),String> 
+                   -> Result<Alignment<T>,DtwError> 
     where F:Fn(&D,&D)->T, T:Float
 {
 
     let x1len = x1.len();
     let x2len = x2.len();
     if x1len == 0 || x2len == 0{
-        return Err("the length of given time series may be empty".to_string())
+        return Err(DtwError::EmptyInput)
     }
     let mut previous = vec![T::infinity();x1len+1];
     let mut current = vec![T::infinity();x1len+1];
@@ -27,5 +29,5 @@ pub fn needleman_dtw<D,F,T>(x1:&[D],x2:&[D],dist:&F,gap:T)
         current = vec![T::infinity();x1len+1];
         current[0] = T::zero();
     }
-    Ok((opt_score,(vec![],vec![]),0))
+    Ok(Alignment::score_only(opt_score,x1len))
 }
@@ -0,0 +1,219 @@
+use super::dtw_chiba::{chiba_region, detect_err_about_band};
+use super::dtw_itakura::{get, region_path};
+use super::Alignment;
+use super::DtwError;
+use num::Float;
+
+/// PrunedDTW (Silva and Batista, 2016): dynamic time warping which skips the cells
+/// whose score exceeds an upper bound of the optimal score, as no optimal path passes them.
+/// The upper bound is the cost of the path along the rescaled diagonal,
+/// i.e. the Euclidean distance when the two series have the same length and dist is squared.
+/// If band is None, the whole matrix is allowed and the result is the same as Mode::Full.
+/// Otherwise, the result is the same as Mode::SakoeChiba(band).
+/// X1 IS QUERY AND X2 IS REFERENCE.
+/// ```rust
+/// use dtw::{dtw, pruned_dtw, Mode};
+/// let x1: Vec<f64> = (0..40).map(|i| (i as f64 / 4.).sin()).collect();
+/// let x2: Vec<f64> = (0..50).map(|i| (i as f64 / 5.).sin()).collect();
+/// let dist = |x: &f64, y: &f64| (x - y).powi(2);
+/// let aln = pruned_dtw(&x1, &x2, &dist, None).unwrap();
+/// assert_eq!(aln, dtw(&x1, &x2, Mode::Full, &dist).unwrap());
+/// let aln = pruned_dtw(&x1, &x2, &dist, Some(5)).unwrap();
+/// assert_eq!(aln, dtw(&x1, &x2, Mode::SakoeChiba(5), &dist).unwrap());
+/// ```
+pub fn pruned_dtw<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    band: Option<usize>,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    ea_pruned_dtw(x1, x2, dist, band, T::infinity())
+}
+
+/// EAPrunedDTW (Herrmann and Webb, 2021): PrunedDTW whose upper bound is also cutoff,
+/// typically the best score so far of a nearest neighbor search.
+/// It returns Err(DtwError::Abandoned{row,..}) as soon as every cell of a row exceeds cutoff,
+/// the same as `dtw_with_cutoff` with Mode::Full or Mode::SakoeChiba(band).
+/// Otherwise, the result is the same as `pruned_dtw`. dist should be nonnegative.
+pub fn ea_pruned_dtw<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    band: Option<usize>,
+    cutoff: T,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    if let Some(band) = band {
+        detect_err_about_band(band)?;
+    }
+    if x1.is_empty() || x2.is_empty() {
+        return Err(DtwError::EmptyInput);
+    }
+    let region = match band {
+        Some(band) => chiba_region(x1.len(), x2.len(), band),
+        None => vec![(0, x2.len() - 1); x1.len()],
+    };
+    pruned_region_dtw(x1, x2, dist, &region, cutoff)
+}
+
+// The same as region_dtw, but only the live cells, whose scores are at most the bound,
+// and their neighbours are computed. The others are left infinite.
+// A cell can only be reached from the live cells of the previous row or from its left,
+// so the cells of a row are computed from the first live column of the previous row,
+// and stop at the first dead cell after the last live column of the previous row.
+// Cells which are at most the bound are never affected, so is the optimal path.
+fn pruned_region_dtw<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    region: &[(usize, usize)],
+    cutoff: T,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    let x1len = x1.len();
+    let bound = diagonal_cost(x1, x2, dist, region).min(cutoff);
+    let mut dp: Vec<Vec<T>> = Vec::with_capacity(x1len);
+    // The live columns [first,last] of the previous row.
+    let (mut first, mut last) = (0, 0);
+    for (i, &(start, end)) in region.iter().enumerate() {
+        let mut row = vec![T::infinity(); end - start + 1];
+        let mut live = None;
+        for j in start.max(first)..=end {
+            let min = if i == 0 && j == 0 {
+                T::zero()
+            } else {
+                let mat = if i > 0 && j > 0 {
+                    get(&dp, region, i - 1, j - 1)
+                } else {
+                    T::infinity()
+                };
+                let gap = if j > start {
+                    row[j - 1 - start]
+                } else {
+                    T::infinity()
+                };
+                let del = if i > 0 {
+                    get(&dp, region, i - 1, j)
+                } else {
+                    T::infinity()
+                };
+                mat.min(gap.min(del))
+            };
+            let score = if min.is_infinite() {
+                min
+            } else {
+                min + dist(&x1[i], &x2[j])
+            };
+            row[j - start] = score;
+            if score <= bound {
+                live = Some(live.map_or((j, j), |(first, _)| (first, j)));
+            } else if i == 0 || j > last {
+                break;
+            }
+        }
+        match live {
+            Some(columns) => {
+                first = columns.0;
+                last = columns.1;
+            }
+            None => {
+                return Err(DtwError::Abandoned {
+                    row: i + 1,
+                    total: x1len,
+                })
+            }
+        }
+        dp.push(row);
+    }
+    if get(&dp, region, x1len - 1, x2.len() - 1) > bound {
+        return Err(DtwError::Abandoned {
+            row: x1len,
+            total: x1len,
+        });
+    }
+    Ok(region_path(&dp, region))
+}
+
+// The cost of the path along the rescaled diagonal, which steps diagonally to the next row
+// if the diagonal moves and then goes along the row to the diagonal.
+// It is infinite if the path leaves the region.
+// The cost is summed in the order of the path, so the optimal score never exceeds it.
+fn diagonal_cost<D, F, T>(x1: &[D], x2: &[D], dist: &F, region: &[(usize, usize)]) -> T
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    let last_row = x1.len() - 1;
+    let last_col = x2.len() - 1;
+    let diagonal = |i: usize| {
+        if i == last_row {
+            last_col
+        } else {
+            (last_col as f64 / last_row as f64 * i as f64).round() as usize
+        }
+    };
+    let mut cost = T::zero();
+    let mut column = 0;
+    for (i, &(start, end)) in region.iter().enumerate() {
+        let next = diagonal(i);
+        let from = if i > 0 && column < next {
+            column + 1
+        } else {
+            column
+        };
+        if from < start || end < next {
+            return T::infinity();
+        }
+        cost = x2[from..=next]
+            .iter()
+            .fold(cost, |acc, y| acc + dist(&x1[i], y));
+        column = next;
+    }
+    cost
+}
+
+#[test]
+fn diagonal_cost_test() {
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let x1 = vec![0, 1, 2];
+    let x2 = vec![0, 0, 1, 1, 2];
+    let full = vec![(0, 4); 3];
+    // (0,0),(1,1),(1,2),(2,3),(2,4)
+    assert_eq!(diagonal_cost(&x1, &x2, &d, &full), 2.);
+    assert_eq!(diagonal_cost(&x1, &x1, &d, &[(0, 2); 3]), 0.);
+    assert_eq!(diagonal_cost(&x1[..1], &x2, &d, &[(0, 4)]), 4.);
+    let narrow = vec![(0, 0), (1, 1), (2, 4)];
+    assert_eq!(diagonal_cost(&x1, &x2, &d, &narrow), f32::INFINITY);
+}
+
+#[test]
+fn pruned_test() {
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let x1 = vec![0, 1, 2, 3, 4];
+    let x2 = vec![0, 0, 1, 1, 2, 2, 3, 3, 4, 4];
+    let aln = pruned_dtw(&x1, &x2, &d, None).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.reference_span, Some((0, 10)));
+    let aln = pruned_dtw(&x1, &x2, &d, Some(3)).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(
+        pruned_dtw(&x1, &x2, &d, Some(4)),
+        Err(DtwError::InvalidBand(4))
+    );
+    assert_eq!(pruned_dtw(&[], &x2, &d, None), Err(DtwError::EmptyInput));
+    let x3 = vec![10, 11, 12];
+    assert_eq!(
+        ea_pruned_dtw(&x1, &x3, &d, None, 5.),
+        Err(DtwError::Abandoned { row: 1, total: 5 })
+    );
+}
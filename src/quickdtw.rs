@@ -1,11 +1,87 @@
+use super::Alignment;
+use super::DtwError;
 use num::Float;
 
-/// quick DTW. Sub dynamic time warping
-pub fn quickdtw<D, F, T>(
+/// quick DTW. Sub dynamic time warping.
+/// The path is not computed, but the location of the optimal alignment
+/// is tracked by carrying the start position along with the DP rows.
+/// It is abandoned when every cell of a row exceeds cutoff.
+pub fn quickdtw<D, F, T>(x1: &[D], x2: &[D], dist: &F, cutoff: T) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    let (scores, starts) = fill_rows(x1, x2, dist, cutoff)?;
+    let (mut opt_score, mut opt_start, mut opt_end) = (T::infinity(), 0, 0);
+    for end in 1..x2.len() + 1 {
+        if scores[end] < opt_score {
+            opt_score = scores[end];
+            opt_start = starts[end];
+            opt_end = end;
+        }
+    }
+    let mut aln = Alignment::score_only(opt_score, x1.len());
+    if !opt_score.is_infinite() {
+        aln.reference_span = Some((opt_start, opt_end));
+    }
+    Ok(aln)
+}
+
+/// The k best non-overlapping hits of sub dynamic time warping, in increasing order of score.
+/// The hits are taken from the scores of the alignments ending at each reference position,
+/// greedily from the best one, skipping a hit if it is closer than min_separation
+/// to an already taken hit (0 means they only must not overlap).
+/// Like quickdtw, the paths are not computed but the reference spans are.
+pub fn subdtw_top_k<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    k: usize,
+    min_separation: usize,
+) -> Result<Vec<Alignment<T>>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    let (scores, starts) = fill_rows(x1, x2, dist, T::infinity())?;
+    // (score, start, end) of the best alignment ending at each position.
+    let mut ends: Vec<_> = (1..x2.len() + 1)
+        .filter(|&end| !scores[end].is_infinite())
+        .map(|end| (scores[end], starts[end], end))
+        .collect();
+    ends.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let mut hits: Vec<(T, usize, usize)> = Vec::with_capacity(k);
+    for (score, start, end) in ends {
+        if hits.len() >= k {
+            break;
+        }
+        let is_separated = hits
+            .iter()
+            .all(|&(_, s, e)| end + min_separation <= s || e + min_separation <= start);
+        if is_separated {
+            hits.push((score, start, end));
+        }
+    }
+    Ok(hits
+        .into_iter()
+        .map(|(score, start, end)| {
+            let mut aln = Alignment::score_only(score, x1.len());
+            aln.reference_span = Some((start, end));
+            aln
+        })
+        .collect())
+}
+
+// Fill the DP table row by row, in the query order.
+// It returns the last row: scores[j] is the optimal score of the alignment ending at
+// the column j, and [starts[j],j) is the aligned region of the reference.
+// It is abandoned when every cell of a row exceeds cutoff.
+fn fill_rows<D, F, T>(
     x1: &[D],
     x2: &[D],
     dist: &F,
-) -> Result<(T, (Vec<usize>, Vec<usize>), usize), String>
+    cutoff: T,
+) -> Result<(Vec<T>, Vec<usize>), DtwError>
 where
     F: Fn(&D, &D) -> T,
     T: Float,
@@ -13,26 +89,97 @@ where
     let x1len = x1.len();
     let x2len = x2.len();
     if x1len == 0 || x2len == 0 {
-        return Err("the length of given time series may be empty".to_string());
+        return Err(DtwError::EmptyInput);
     }
-    let zero = T::zero();
     let inf = T::infinity();
-    let mut previous = vec![inf; x1len + 1];
-    let mut current = vec![inf; x1len + 1];
-    previous[0] = zero;
-    current[0] = zero;
-    let mut opt_score = inf;
-    for j in 1..x2len + 1 {
-        for i in 1..x1len + 1 {
+    // previous[j] is the optimal score of the alignment ending at (i-1,j),
+    // and previous_start[j] is the reference position where it starts.
+    // The alignments from the top row start at the column they leave the row.
+    let mut previous = vec![T::zero(); x2len + 1];
+    let mut current = vec![inf; x2len + 1];
+    let mut previous_start = vec![0; x2len + 1];
+    let mut current_start = vec![0; x2len + 1];
+    for i in 1..x1len + 1 {
+        let mut is_over = true;
+        for j in 1..x2len + 1 {
+            let (mat_start, del_start) = if i == 1 {
+                (j - 1, j - 1)
+            } else {
+                (previous_start[j - 1], previous_start[j])
+            };
             let cost = dist(&x1[i - 1], &x2[j - 1]);
-            let min: T = previous[i].min(previous[i - 1].min(current[i - 1]));
-            current[i] = if !min.is_infinite() { min + cost } else { min };
+            let (min, start) = min_with_start(
+                (previous[j - 1], mat_start),
+                (current[j - 1], current_start[j - 1]),
+                (previous[j], del_start),
+            );
+            current[j] = if !min.is_infinite() { min + cost } else { min };
+            current_start[j] = start;
+            is_over &= current[j] > cutoff;
+        }
+        if is_over {
+            return Err(DtwError::Abandoned {
+                row: i,
+                total: x1len,
+            });
         }
-        opt_score = opt_score.min(current[x1len]);
-        previous.clear();
-        previous.append(&mut current);
-        current = vec![inf; x1len + 1];
-        current[0] = zero;
+        std::mem::swap(&mut previous, &mut current);
+        std::mem::swap(&mut previous_start, &mut current_start);
+        current[0] = inf;
+    }
+    Ok((previous, previous_start))
+}
+
+// Select the smallest of match, insertion, and deletion along with its start position
+// (or any other information carried along the DP). Ties are resolved in this order.
+#[inline]
+pub fn min_with_start<T: Float, S>(mat: (T, S), ins: (T, S), del: (T, S)) -> (T, S) {
+    if mat.0 <= ins.0 && mat.0 <= del.0 {
+        mat
+    } else if ins.0 <= del.0 {
+        ins
+    } else {
+        del
+    }
+}
+
+#[test]
+fn location_test() {
+    let x1 = vec![1, 2, 3];
+    let x2 = vec![0, 0, 1, 2, 2, 3, 0];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = quickdtw(&x1, &x2, &d, f32::INFINITY).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.reference_span, Some((2, 6)));
+    assert!(!aln.has_path());
+}
+
+#[test]
+fn location_at_edge() {
+    let x1 = vec![1, 2];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = quickdtw(&x1, &[1, 2, 0, 0], &d, f32::INFINITY).unwrap();
+    assert_eq!(aln.reference_span, Some((0, 2)));
+    let aln = quickdtw(&x1, &[0, 0, 1, 2], &d, f32::INFINITY).unwrap();
+    assert_eq!(aln.reference_span, Some((2, 4)));
+}
+
+#[test]
+fn top_k_test() {
+    let x1 = vec![1, 2, 3];
+    let x2 = vec![1, 2, 3, 0, 0, 1, 2, 2, 3, 0, 1, 3];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let hits = subdtw_top_k(&x1, &x2, &d, 3, 0).unwrap();
+    assert_eq!(hits.len(), 3);
+    assert_eq!(hits[0].reference_span, Some((0, 3)));
+    assert_eq!(hits[1].reference_span, Some((5, 9)));
+    assert_eq!(hits[2].reference_span, Some((10, 12)));
+    assert_eq!(hits[2].score, 1.);
+    for w in hits.windows(2) {
+        assert!(w[0].score <= w[1].score);
     }
-    Ok((opt_score, (vec![], vec![]), 0))
+    // The third hit is too close to the second one, and no other hit remains.
+    let hits = subdtw_top_k(&x1, &x2, &d, 3, 2).unwrap();
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[1].reference_span, Some((5, 9)));
 }
@@ -0,0 +1,171 @@
+use super::StepPattern;
+use super::TRACE;
+use num::Float;
+/// Result of dynamic time warping.
+///
+/// Spans are half-open intervals [start,end) of indices.
+/// Some modes compute only the score, or only the location of the alignment.
+/// In that case, the corresponding field is None rather than an empty path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alignment<T> {
+    /// The optimal score.
+    pub score: T,
+    /// Warping path as (query indices, reference indices). None if the path was not computed.
+    pub path: Option<TRACE>,
+    /// The aligned region of the query.
+    pub query_span: (usize, usize),
+    /// The aligned region of the reference. None if the location was not computed.
+    pub reference_span: Option<(usize, usize)>,
+}
+
+impl<T> Alignment<T> {
+    /// Make an alignment from a warping path. Both spans are taken from the path.
+    pub fn from_path(score: T, path: TRACE) -> Self {
+        debug_assert!(path.0.len() == path.1.len() && !path.0.is_empty());
+        let span = |xs: &[usize]| (xs[0], xs[xs.len() - 1] + 1);
+        let query_span = span(&path.0);
+        let reference_span = Some(span(&path.1));
+        Alignment {
+            score,
+            path: Some(path),
+            query_span,
+            reference_span,
+        }
+    }
+    /// Make an alignment only with its score. The whole query is assumed to be aligned.
+    pub fn score_only(score: T, query_len: usize) -> Self {
+        Alignment {
+            score,
+            path: None,
+            query_span: (0, query_len),
+            reference_span: None,
+        }
+    }
+    /// Whether the warping path was computed.
+    pub fn has_path(&self) -> bool {
+        self.path.is_some()
+    }
+    /// Whether the location in the reference was computed.
+    pub fn has_reference_span(&self) -> bool {
+        self.reference_span.is_some()
+    }
+    /// The index of the reference where the alignment starts, if computed.
+    pub fn reference_start(&self) -> Option<usize> {
+        self.reference_span.map(|(start, _)| start)
+    }
+    /// The index of the reference where the alignment ends (exclusive), if computed.
+    pub fn reference_end(&self) -> Option<usize> {
+        self.reference_span.map(|(_, end)| end)
+    }
+}
+
+/// How to normalize the score of an alignment, so that the scores of
+/// queries with different lengths can be compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalization {
+    /// Divide by the length of the aligned query.
+    QueryLength,
+    /// Divide by the number of cells in the warping path.
+    PathLength,
+}
+
+impl<T: Float> Alignment<T> {
+    /// The score normalized as specified.
+    /// None for Normalization::PathLength if the path was not computed.
+    pub fn normalized(&self, by: Normalization) -> Option<T> {
+        let len = match by {
+            Normalization::QueryLength => Some(self.query_span.1 - self.query_span.0),
+            Normalization::PathLength => self.path.as_ref().map(|p| p.0.len()),
+        };
+        len.and_then(T::from).map(|z| self.score / z)
+    }
+    /// The local cost of each cell along the warping path, to locate poorly matching regions.
+    /// x1, x2, and dist should be the ones given to compute this alignment.
+    /// None if the path was not computed. Note that the costs are not weighted by the step pattern.
+    pub fn local_costs<D, F>(&self, x1: &[D], x2: &[D], dist: &F) -> Option<Vec<T>>
+    where
+        F: Fn(&D, &D) -> T,
+    {
+        self.path.as_ref().map(|(xpath, ypath)| {
+            xpath
+                .iter()
+                .zip(ypath.iter())
+                .map(|(&i, &j)| dist(&x1[i], &x2[j]))
+                .collect()
+        })
+    }
+    /// The score divided by the normalization factor of the step pattern.
+    /// None if the pattern has no normalization factor, or the factor
+    /// depends on the reference span which was not computed.
+    pub fn normalized_score(&self, step: StepPattern) -> Option<T> {
+        let n = self.query_span.1 - self.query_span.0;
+        let m = self.reference_span.map(|(s, e)| e - s).unwrap_or(0);
+        match step {
+            StepPattern::Symmetric2 | StepPattern::SymmetricP(..) if !self.has_reference_span() => {
+                None
+            }
+            _ => step
+                .normalizer(n, m)
+                .and_then(T::from)
+                .map(|z| self.score / z),
+        }
+    }
+}
+
+#[test]
+fn from_path_test() {
+    let aln = Alignment::from_path(1., (vec![0, 1, 1, 2], vec![3, 4, 5, 5]));
+    assert!(aln.has_path() && aln.has_reference_span());
+    assert_eq!(aln.query_span, (0, 3));
+    assert_eq!(aln.reference_span, Some((3, 6)));
+    assert_eq!(aln.reference_start(), Some(3));
+    assert_eq!(aln.reference_end(), Some(6));
+}
+
+#[test]
+fn score_only_test() {
+    let aln = Alignment::score_only(1., 10);
+    assert!(!aln.has_path() && !aln.has_reference_span());
+    assert_eq!(aln.query_span, (0, 10));
+    assert_eq!(aln.reference_start(), None);
+}
+
+#[test]
+fn normalized_score_test() {
+    let aln = Alignment::from_path(10., (vec![0, 1, 1, 2], vec![3, 4, 5, 5]));
+    assert_eq!(aln.normalized_score(StepPattern::Symmetric1), None);
+    assert_eq!(
+        aln.normalized_score(StepPattern::Symmetric2),
+        Some(10. / 6.)
+    );
+    assert_eq!(
+        aln.normalized_score(StepPattern::Asymmetric),
+        Some(10. / 3.)
+    );
+    let aln = Alignment::score_only(10., 5);
+    assert_eq!(aln.normalized_score(StepPattern::Symmetric2), None);
+    assert_eq!(
+        aln.normalized_score(StepPattern::AsymmetricP(1, 1)),
+        Some(2.)
+    );
+}
+
+#[test]
+fn normalized_test() {
+    let aln = Alignment::from_path(10., (vec![0, 1, 1, 2, 3], vec![3, 4, 5, 5, 6]));
+    assert_eq!(aln.normalized(Normalization::QueryLength), Some(2.5));
+    assert_eq!(aln.normalized(Normalization::PathLength), Some(2.));
+    let aln = Alignment::score_only(10., 5);
+    assert_eq!(aln.normalized(Normalization::QueryLength), Some(2.));
+    assert_eq!(aln.normalized(Normalization::PathLength), None);
+}
+
+#[test]
+fn local_costs_test() {
+    let x1 = vec![1, 2, 3];
+    let x2 = vec![0, 1, 3, 3];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = Alignment::from_path(1., (vec![0, 1, 2, 2], vec![1, 2, 2, 3]));
+    assert_eq!(aln.local_costs(&x1, &x2, &d), Some(vec![0., 1., 0., 0.]));
+    assert_eq!(Alignment::score_only(1., 3).local_costs(&x1, &x2, &d), None);
+}
@@ -1,9 +1,12 @@
+use super::Alignment;
+use super::DtwError;
 use num::Float;
-use num::Num;
 use order_stat::kth_by;
 const NUM_SCOUT: usize = 20;
 const PACK_SIZE: usize = 5;
-/// Scouting sub dynamic time warping with threshold bounding
+/// Scouting sub dynamic time warping with threshold bounding.
+/// It returns Err(DtwError::Abandoned{..}) when the scouting or
+/// every candidate reaches the threshold.
 pub fn scouting_threshold_dtw<D, F, T>(
     x1: &[D],
     x2: &[D],
@@ -11,11 +14,10 @@ pub fn scouting_threshold_dtw<D, F, T>(
     num_scouts: Option<usize>,
     num_packs: Option<usize>,
     threshold: T,
-) -> Result<(T, (Vec<usize>, Vec<usize>), usize), String>
+) -> Result<Alignment<T>, DtwError>
 where
     F: Fn(&D, &D) -> T,
     T: Float,
-    D: Num + Copy,
 {
     // x1 is query, x2 is reference.
     let x1len = x1.len();
@@ -34,29 +36,71 @@ where
         num_scouts,
         threshold,
         x1len * 3 / 2,
-    );
+    )?;
     if candidates.is_empty() {
-        // early return.
-        return Err("Early return".to_string());
+        return Err(DtwError::NoCandidates);
     }
     let mut opt = T::infinity();
     let mut optcand = None;
+    let mut abandoned = None;
     for (start, end) in candidates {
-        if let Ok(res) = super::thresholddtw(x1, &x2[start..end], dist, threshold) {
-            if res.0 < threshold {
-                return Ok(res);
-            } else if res.0 < opt {
-                opt = res.0;
-                optcand = Some(res);
+        match super::thresholddtw(x1, &x2[start..end], dist, threshold) {
+            Ok(mut res) => {
+                // the location should be relative to the entire reference.
+                res.reference_span = res.reference_span.map(|(s, e)| (s + start, e + start));
+                if res.score < threshold {
+                    return Ok(res);
+                } else if res.score < opt {
+                    opt = res.score;
+                    optcand = Some(res);
+                }
             }
+            Err(DtwError::Abandoned { row, total }) => {
+                // keep the candidate which went farthest.
+                abandoned = match abandoned {
+                    Some(DtwError::Abandoned { row: prev, .. }) if prev >= row => abandoned,
+                    _ => Some(DtwError::Abandoned { row, total }),
+                };
+            }
+            Err(e) => return Err(e),
         }
     }
-    match optcand {
-        Some(res) => Ok(res),
-        None => Err("There's no candidates. This error should not happen.".to_string()),
+    match (optcand, abandoned) {
+        (Some(res), _) => Ok(res),
+        (None, Some(e)) => Err(e),
+        (None, None) => Err(DtwError::NoCandidates),
     }
 }
 
+/// scouting_threshold_dtw which also reports the warping path of the best candidate.
+/// The path is the optimal alignment between the query and the reported region
+/// of the reference, and its reference indices are positions of the entire reference.
+/// The score is recomputed along with the path by the full alignment to the region,
+/// which is abandoned by the threshold as well.
+pub fn scouting_threshold_dtw_with_path<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    num_scouts: Option<usize>,
+    num_packs: Option<usize>,
+    threshold: T,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    let aln = scouting_threshold_dtw(x1, x2, dist, num_scouts, num_packs, threshold)?;
+    let (start, end) = match aln.reference_span {
+        Some(span) => span,
+        None => return Ok(aln),
+    };
+    // Recover the path of the best candidate.
+    let aln = super::ea_pruned_dtw(x1, &x2[start..end], dist, None, threshold)?;
+    let (x1path, x2path) = aln.path.expect("the full alignment has a path");
+    let x2path = x2path.into_iter().map(|j| j + start).collect();
+    Ok(Alignment::from_path(aln.score, (x1path, x2path)))
+}
+
 /// Scouting sub dynamic time warping.
 /// This algorithm first compute "mini-" query to find candidates for "entire" query.
 /// To determine start position, dynanic programming table is filled in a reverse order.
@@ -66,11 +110,10 @@ pub fn scouting_dtw<D, F, T>(
     dist: &F,
     num_scouts: Option<usize>,
     num_packs: Option<usize>,
-) -> Result<(T, (Vec<usize>, Vec<usize>), usize), String>
+) -> Result<Alignment<T>, DtwError>
 where
     F: Fn(&D, &D) -> T,
     T: Float,
-    D: Num + Copy,
 {
     scouting_threshold_dtw(x1, x2, dist, num_scouts, num_packs, T::infinity())
 }
@@ -87,17 +130,16 @@ fn scouting<D, F, T>(
     num_scout: usize,
     threshold: T,
     subreflen: usize,
-) -> Vec<(usize, usize)>
+) -> Result<Vec<(usize, usize)>, DtwError>
 where
     F: Fn(&D, &D) -> T,
     T: Float,
-    D: Num + Copy,
 {
     let x1len = x1.len(); // length of scout
     let x2len = x2.len();
     if x1len == 0 || x2len == 0 {
         //eprintln!("query:{},reference:{}",x1len,x2len);
-        return vec![];
+        return Err(DtwError::EmptyInput);
     }
     // make sure that this scouting procedure will be executed in
     // "reverse" manner.
@@ -115,7 +157,10 @@ where
         }
         if early_return {
             //eprintln!("early return at :{}/{}",i,x1len);
-            return vec![];
+            return Err(DtwError::Abandoned {
+                row: i,
+                total: x1len,
+            });
         }
         for k in 0..x2len + 1 {
             previous[k] = current[k];
@@ -140,7 +185,7 @@ where
         })
         .collect();
     if result.is_empty() {
-        vec![]
+        Ok(vec![])
     } else {
         // sort the scouts in increasing order with respect to index;
         result.sort_by(|a, b| (a.1).cmp(&b.1));
@@ -148,7 +193,7 @@ where
         let mut result = remove_overlapping_scouts(&result, subreflen, x2len);
         // sort the scouts in increasing order with respect to score.
         result.sort_by(|a, b| (a.0).partial_cmp(&b.0).unwrap());
-        result.into_iter().map(|a| (a.1, a.2)).collect()
+        Ok(result.into_iter().map(|a| (a.1, a.2)).collect())
     }
 }
 
@@ -0,0 +1,143 @@
+use super::quickdtw::min_with_start;
+use super::Alignment;
+use super::DtwError;
+use num::Float;
+
+/// Stream monitoring by sub dynamic time warping (SPRING).
+/// The reference is fed one sample at a time, and the matcher reports
+/// non-overlapping alignments whose scores are not larger than the threshold,
+/// as soon as no better overlapping alignment can appear.
+/// Only the current DP column and the start positions are kept,
+/// thus the memory usage does not depend on the length of the reference.
+/// The reported alignments have reference spans in the stream coordinate but no paths.
+pub struct SpringMatcher<D, F, T> {
+    query: Vec<D>,
+    dist: F,
+    threshold: T,
+    // column[i] is the optimal score of the alignment ending at (i,position-1).
+    // column[0] is the star-padding row.
+    column: Vec<T>,
+    // start[i] is the stream position where the alignment above starts.
+    start: Vec<usize>,
+    position: usize,
+    // (score,start,end) of the best alignment not reported yet.
+    candidate: Option<(T, usize, usize)>,
+}
+
+impl<D, F, T> SpringMatcher<D, F, T>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    /// Make a matcher for the query. It returns Err(DtwError::EmptyInput) for an empty query.
+    pub fn new(query: Vec<D>, dist: F, threshold: T) -> Result<Self, DtwError> {
+        if query.is_empty() {
+            return Err(DtwError::EmptyInput);
+        }
+        let len = query.len();
+        let mut column = vec![T::infinity(); len + 1];
+        column[0] = T::zero();
+        Ok(SpringMatcher {
+            query,
+            dist,
+            threshold,
+            column,
+            start: vec![0; len + 1],
+            position: 0,
+            candidate: None,
+        })
+    }
+    /// The number of samples fed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+    /// Feed a sample. It returns an alignment when one is finalized by this sample.
+    pub fn push(&mut self, sample: &D) -> Option<Alignment<T>> {
+        let t = self.position;
+        self.position += 1;
+        // Update the column in place. diag keeps the previous value of column[i-1].
+        self.start[0] = t;
+        let mut diag = (self.column[0], self.start[0]);
+        for i in 1..self.column.len() {
+            let up = (self.column[i], self.start[i]);
+            let (min, start) = min_with_start(diag, up, (self.column[i - 1], self.start[i - 1]));
+            let cost = (self.dist)(&self.query[i - 1], sample);
+            diag = up;
+            self.column[i] = if min.is_infinite() { min } else { min + cost };
+            self.start[i] = start;
+        }
+        let mut reported = None;
+        if let Some((score, start, end)) = self.candidate {
+            let is_final = self
+                .column
+                .iter()
+                .zip(self.start.iter())
+                .skip(1)
+                .all(|(&d, &s)| d >= score || s >= end);
+            if is_final {
+                let mut aln = Alignment::score_only(score, self.query.len());
+                aln.reference_span = Some((start, end));
+                reported = Some(aln);
+                self.candidate = None;
+                // Alignments overlapping the reported one are discarded.
+                for (d, &s) in self.column.iter_mut().zip(self.start.iter()).skip(1) {
+                    if s < end {
+                        *d = T::infinity();
+                    }
+                }
+            }
+        }
+        let last = self.query.len();
+        let score = self.column[last];
+        if score <= self.threshold && self.candidate.map(|c| score < c.0).unwrap_or(true) {
+            self.candidate = Some((score, self.start[last], t + 1));
+        }
+        reported
+    }
+    /// Feed samples. It returns the alignments finalized during the feeding.
+    pub fn extend(&mut self, samples: &[D]) -> Vec<Alignment<T>> {
+        samples.iter().filter_map(|x| self.push(x)).collect()
+    }
+    /// Close the stream and return the alignment which is not reported yet, if any.
+    pub fn finish(self) -> Option<Alignment<T>> {
+        let len = self.query.len();
+        self.candidate.map(|(score, start, end)| {
+            let mut aln = Alignment::score_only(score, len);
+            aln.reference_span = Some((start, end));
+            aln
+        })
+    }
+}
+
+#[test]
+fn spring_test() {
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let mut matcher = SpringMatcher::new(vec![1, 2, 3], d, 0.5).unwrap();
+    let stream = vec![0, 1, 2, 3, 0, 0, 1, 1, 2, 3, 3, 0];
+    let hits = matcher.extend(&stream);
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].reference_span, Some((1, 4)));
+    assert_eq!(hits[1].reference_span, Some((7, 10)));
+    assert!(hits.iter().all(|h| h.score == 0.));
+    assert_eq!(matcher.position(), stream.len());
+    assert!(matcher.finish().is_none());
+}
+
+#[test]
+fn spring_finish() {
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let mut matcher = SpringMatcher::new(vec![1, 2, 3], d, 1.).unwrap();
+    assert!(matcher.extend(&[0, 0, 1, 2, 3]).is_empty());
+    let aln = matcher.finish().unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.reference_span, Some((2, 5)));
+}
+
+#[test]
+fn spring_empty() {
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    assert_eq!(
+        SpringMatcher::new(vec![], d, 1.).err(),
+        Some(DtwError::EmptyInput)
+    );
+}
@@ -0,0 +1,293 @@
+use super::Alignment;
+use super::DtwError;
+use num::Float;
+
+/// Step pattern, i.e., the local recursion of the dynamic programming.
+///
+/// The weighted patterns have a natural normalization factor, so that the
+/// scores of series with different lengths can be compared. See `normalizer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepPattern {
+    /// g(i,j) = d(i,j) + min(g(i-1,j-1), g(i,j-1), g(i-1,j)). The default of `dtw`.
+    Symmetric1,
+    /// g(i,j) = min(g(i-1,j-1) + 2d(i,j), g(i,j-1) + d(i,j), g(i-1,j) + d(i,j)).
+    /// Normalized by N+M.
+    Symmetric2,
+    /// g(i,j) = d(i,j) + min(g(i-1,j), g(i-1,j-1), g(i-1,j-2)).
+    /// The query advances at every step, thus the reference can be at most twice as long.
+    /// Normalized by N, the length of the query.
+    Asymmetric,
+    /// Rabiner-Juang (Sakoe-Chiba) slope constraint P = m/n with symmetric weights:
+    /// After n consecutive horizontal or vertical moves, at least m diagonal moves are needed.
+    /// Diagonal moves are weighted by 2, the others by 1. Normalized by N+M.
+    /// SymmetricP(0,_) is the same as Symmetric2.
+    SymmetricP(usize, usize),
+    /// Rabiner-Juang (Sakoe-Chiba) slope constraint P = m/n with asymmetric weights:
+    /// Moves are weighted by how far they advance in the query. Normalized by N.
+    AsymmetricP(usize, usize),
+}
+
+// A step from (i-di,j-dj) to (i,j).
+// cells are the cells visited by the step, as (offset_i,offset_j,weight)
+// counted back from (i,j). The local cost of each cell is multiplied by its weight.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Step {
+    pub di: usize,
+    pub dj: usize,
+    pub cells: Vec<(usize, usize, usize)>,
+}
+
+impl StepPattern {
+    // The steps in the order of preference on ties:
+    // diagonal, then horizontal (along the reference), then vertical.
+    pub(crate) fn steps(&self) -> Vec<Step> {
+        let single = |di, dj, w| Step {
+            di,
+            dj,
+            cells: vec![(0, 0, w)],
+        };
+        match *self {
+            StepPattern::Symmetric1 => vec![single(1, 1, 1), single(0, 1, 1), single(1, 0, 1)],
+            StepPattern::Symmetric2 => vec![single(1, 1, 2), single(0, 1, 1), single(1, 0, 1)],
+            StepPattern::Asymmetric => vec![single(1, 1, 1), single(1, 2, 1), single(1, 0, 1)],
+            StepPattern::SymmetricP(m, n) => slope_steps(m, n, (2, 1, 1)),
+            StepPattern::AsymmetricP(m, n) => slope_steps(m, n, (1, 0, 1)),
+        }
+    }
+    /// The normalization factor for a query of length n aligned to m reference positions.
+    /// None for Symmetric1, whose score has no natural normalization.
+    pub fn normalizer(&self, n: usize, m: usize) -> Option<usize> {
+        match *self {
+            StepPattern::Symmetric1 => None,
+            StepPattern::Symmetric2 | StepPattern::SymmetricP(..) => Some(n + m),
+            StepPattern::Asymmetric | StepPattern::AsymmetricP(..) => Some(n),
+        }
+    }
+}
+
+impl std::fmt::Display for StepPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            StepPattern::Symmetric1 => write!(f, "Symmetric1"),
+            StepPattern::Symmetric2 => write!(f, "Symmetric2"),
+            StepPattern::Asymmetric => write!(f, "Asymmetric"),
+            StepPattern::SymmetricP(m, n) => write!(f, "SymmetricP({}/{})", m, n),
+            StepPattern::AsymmetricP(m, n) => write!(f, "AsymmetricP({}/{})", m, n),
+        }
+    }
+}
+
+// Steps of slope constraint P = m/n: the diagonal move, and
+// m diagonal moves followed by k horizontal (or vertical) moves for k = 1..n.
+// weights are (diagonal, horizontal, vertical).
+fn slope_steps(m: usize, n: usize, weights: (usize, usize, usize)) -> Vec<Step> {
+    let (wd, wh, wv) = weights;
+    // Without diagonal moves, only a single axial move makes sense.
+    let n = if m == 0 { 1 } else { n.max(1) };
+    let axial = |k: usize, is_horizontal: bool| {
+        let w = if is_horizontal { wh } else { wv };
+        let flip = |a: usize, b: usize| if is_horizontal { (a, b) } else { (b, a) };
+        let mut cells: Vec<_> = (0..k)
+            .map(|t| {
+                let (a, b) = flip(0, t);
+                (a, b, w)
+            })
+            .collect();
+        cells.extend((0..m).map(|s| {
+            let (a, b) = flip(s, k + s);
+            (a, b, wd)
+        }));
+        let (di, dj) = flip(m, k + m);
+        Step { di, dj, cells }
+    };
+    let mut steps = vec![Step {
+        di: 1,
+        dj: 1,
+        cells: vec![(0, 0, wd)],
+    }];
+    steps.extend((1..=n).map(|k| axial(k, true)));
+    steps.extend((1..=n).map(|k| axial(k, false)));
+    steps
+}
+
+// The weights of the cells of each step, converted to T.
+pub(crate) fn step_weights<T: Float>(steps: &[Step]) -> Vec<Vec<T>> {
+    steps
+        .iter()
+        .map(|s| {
+            s.cells
+                .iter()
+                .map(|&(_, _, w)| T::from(w).unwrap())
+                .collect()
+        })
+        .collect()
+}
+
+/// Full or sub dynamic time warping with a step pattern.
+/// X1 IS QUERY AND X2 IS REFERENCE.
+pub(crate) fn pattern_dtw<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    pattern: StepPattern,
+    is_sub: bool,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    let x1len = x1.len();
+    let x2len = x2.len();
+    if x1len == 0 || x2len == 0 {
+        return Err(DtwError::EmptyInput);
+    }
+    let steps = pattern.steps();
+    let weights: Vec<Vec<T>> = step_weights(&steps);
+    let mut res = vec![vec![T::infinity(); x2len + 1]; x1len + 1];
+    // choice[i][j] is the index of the step reaching (i,j).
+    let mut choice = vec![vec![0; x2len + 1]; x1len + 1];
+    res[0][0] = T::zero();
+    if is_sub {
+        for x in res[0].iter_mut() {
+            *x = T::zero();
+        }
+    }
+    for i in 1..x1len + 1 {
+        for j in 1..x2len + 1 {
+            let mut opt = (T::infinity(), 0);
+            for (idx, (step, ws)) in steps.iter().zip(weights.iter()).enumerate() {
+                // Every visited cell should be inside the matrix.
+                if step.di > i
+                    || step.dj > j
+                    || step.cells.iter().any(|&(a, b, _)| a >= i || b >= j)
+                {
+                    continue;
+                }
+                let prev = res[i - step.di][j - step.dj];
+                if prev.is_infinite() {
+                    continue;
+                }
+                let score = step
+                    .cells
+                    .iter()
+                    .zip(ws.iter())
+                    .filter(|&(&(_, _, w), _)| w > 0)
+                    .fold(prev, |acc, (&(a, b, _), &w)| {
+                        acc + w * dist(&x1[i - 1 - a], &x2[j - 1 - b])
+                    });
+                if score < opt.0 {
+                    opt = (score, idx);
+                }
+            }
+            res[i][j] = opt.0;
+            choice[i][j] = opt.1;
+        }
+    }
+    let (mut j, score) =
+        if is_sub {
+            res[x1len].iter().enumerate().skip(1).fold(
+                (0, T::infinity()),
+                |(idx, acc), (j, &score)| if acc < score { (idx, acc) } else { (j, score) },
+            )
+        } else {
+            (x2len, res[x1len][x2len])
+        };
+    if score.is_infinite() {
+        return Err(DtwError::NoPath);
+    }
+    let (mut x1path, mut x2path) = (vec![], vec![]);
+    let mut i = x1len;
+    while i > 0 {
+        let step = &steps[choice[i][j]];
+        for &(a, b, _) in &step.cells {
+            x1path.push(i - 1 - a);
+            x2path.push(j - 1 - b);
+        }
+        i -= step.di;
+        j -= step.dj;
+    }
+    x1path.reverse();
+    x2path.reverse();
+    Ok(Alignment::from_path(score, (x1path, x2path)))
+}
+
+#[test]
+fn slope_steps_test() {
+    // P = 1
+    let steps = StepPattern::SymmetricP(1, 1).steps();
+    assert_eq!(steps.len(), 3);
+    assert_eq!(
+        steps[1],
+        Step {
+            di: 1,
+            dj: 2,
+            cells: vec![(0, 0, 1), (0, 1, 2)]
+        }
+    );
+    assert_eq!(
+        steps[2],
+        Step {
+            di: 2,
+            dj: 1,
+            cells: vec![(0, 0, 1), (1, 0, 2)]
+        }
+    );
+    // P = 0 is the same as symmetric2.
+    assert_eq!(
+        StepPattern::SymmetricP(0, 3).steps(),
+        StepPattern::Symmetric2.steps()
+    );
+    // P = 1/2
+    let steps = StepPattern::AsymmetricP(1, 2).steps();
+    assert_eq!(steps.len(), 5);
+    assert_eq!(
+        steps[2],
+        Step {
+            di: 1,
+            dj: 3,
+            cells: vec![(0, 0, 0), (0, 1, 0), (0, 2, 1)]
+        }
+    );
+}
+
+#[test]
+fn symmetric2_test() {
+    let x1 = vec![0, 1, 2];
+    let x2 = vec![0, 1, 1, 2];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = pattern_dtw(&x1, &x2, &d, StepPattern::Symmetric2, false).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.path, Some((vec![0, 1, 1, 2], vec![0, 1, 2, 3])));
+    let aln = pattern_dtw(&[1], &[0, 0, 0], &d, StepPattern::Symmetric2, false).unwrap();
+    // The diagonal move is doubled.
+    assert_eq!(aln.score, 4.);
+}
+
+#[test]
+fn asymmetric_test() {
+    let x1 = vec![0, 1, 2];
+    let x2 = vec![0, 5, 1, 5, 2];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = pattern_dtw(&x1, &x2, &d, StepPattern::Asymmetric, false).unwrap();
+    // The reference positions with 5 are skipped.
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.path, Some((vec![0, 1, 2], vec![0, 2, 4])));
+    // The reference is too long.
+    let res = pattern_dtw(&x1, &[0; 7], &d, StepPattern::Asymmetric, false);
+    assert_eq!(res.err(), Some(DtwError::NoPath));
+}
+
+#[test]
+fn slope_constraint_test() {
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    // P = 1 forbids two consecutive horizontal moves.
+    let x1 = vec![0, 1, 2];
+    let x2 = vec![0, 1, 1, 1, 2];
+    let free = pattern_dtw(&x1, &x2, &d, StepPattern::SymmetricP(0, 1), false).unwrap();
+    let constrained = pattern_dtw(&x1, &x2, &d, StepPattern::SymmetricP(1, 1), false).unwrap();
+    assert_eq!(free.score, 0.);
+    assert!(constrained.score > 0.);
+    let (xpath, ypath) = constrained.path.unwrap();
+    assert_eq!((xpath[0], ypath[0]), (0, 0));
+    assert_eq!((xpath[xpath.len() - 1], ypath[ypath.len() - 1]), (2, 4));
+}
@@ -8,28 +8,38 @@ fn test_of_test(){
 fn test_dtw(){
     let x1 = vec![1.,1.];
     let x2 = vec![2.,2.];
-    let res = dtw::normal_dtw(&x1,&x2);
-    debug_assert!((res-2.0).abs() < 0.001,"{}",dtw::normal_dtw(&x1,&x2));
+    let res = normal_dtw(&x1,&x2);
+    debug_assert!((res-2.0).abs() < 0.001,"{}",normal_dtw(&x1,&x2));
 }
 #[test]
 fn test_dtw2(){
     let x1 = vec![1.];
     let x2 = vec![1.];
-    debug_assert!(dtw::normal_dtw(&x1,&x2) < 0.001,"{}",dtw::normal_dtw(&x1,&x2));
+    debug_assert!(normal_dtw(&x1,&x2) < 0.001,"{}",normal_dtw(&x1,&x2));
+}
+
+#[test]
+fn full_path_on_ties(){
+    // At the last cell, match and gap tie below deletion.
+    // The deletion used to be taken, and the path cost 2 for the score 1.
+    let dist = |x:&f32,y:&f32| (x-y).abs();
+    let aln = dtw(&[0.,0.],&[0.,1.],Mode::Full,&dist).unwrap();
+    assert_eq!(aln.score,1.);
+    assert_eq!(aln.path,Some((vec![0,1],vec![0,1])));
 }
 
 #[test]
 fn test_dtw3(){
     let x = vec![1.,2.,3.,4.,5.];
     let y = vec![2.,3.,4.];
-    let res = dtw::normal_dtw(&x,&y);
+    let res = normal_dtw(&x,&y);
     debug_assert!((res - 2.).abs() < 0.1,"{}",res)
 }
 #[test]
 fn test_dtw4(){
     let x = vec![1., 1., 1., 2., 2., 2., 2.];
     let y = vec![1., 1., 2.,2.];
-    let res = dtw::subdtw(&y,&x);
+    let res = subdtw(&y,&x);
     println!("{}",res);
     debug_assert!(res < 0.001,"{}",res);
 }
@@ -37,7 +47,7 @@ fn test_dtw4(){
 fn test_dtw5(){
     let x = vec![1., 1., 1.,1.,1.];//,2., 2., 2., 2., 3., 2., 0.];
     let y = vec![2.,0., 0., 1.];//, 1., 2., 4., 2., 1., 2., 0.];
-    let res = dtw::subdtw(&x,&y);
+    let res = subdtw(&x,&y);
     debug_assert!(res -3.0 < 0.001,"{}",res);
 }
 
@@ -45,7 +55,7 @@ fn test_dtw5(){
 fn test_dtw6(){
     let x = vec![1., 1., 4.,5.,1.];//,2., 2., 2., 2., 3., 2., 0.];
     let y = vec![6.,6.];//, 1., 2., 4., 2., 1., 2., 0.];
-    let res = dtw::subdtw(&y,&x);
+    let res = subdtw(&y,&x);
     debug_assert!(res -3.0 < 0.001,"{}",res);
     debug_assert!(res > 0.,"{}",res);
 }
@@ -55,7 +65,9 @@ fn test_dtw7(){
     let x = vec![1., 1., 4.,5.,1.];
     let y = vec![6.,6.];
     let d = |x:&f32,y:&f32| (x-y).powi(2);
-    let (res,(ypath,xpath),start) = dtw::dtw(&y,&x,dtw::Mode::Sub,&d).unwrap();
+    let aln = dtw(&y,&x,Mode::Sub,&d).unwrap();
+    let (res,start) = (aln.score,aln.reference_start().unwrap());
+    let (ypath,xpath) = aln.path.unwrap();
     println!("{},{}\n{:?}\n{:?}",start,res,xpath,ypath);
     debug_assert!(start == 3 ,"{},{}\n{:?}\n{:?}",start,res,xpath,ypath);
 }
@@ -65,7 +77,7 @@ fn test_dtw8(){
     let _ = normalize::NormalizeType::Z;
     let x = vec![1., 1., 4.,5.,1.];//,2., 2., 2., 2., 3., 2., 0.];
     let y = vec![6.,6.];//, 1., 2., 4., 2., 1., 2., 0.];
-    let (res,(ypath,xpath)) = dtw::subdtw_with_path(&y,&x);
+    let (res,(ypath,xpath)) = subdtw_with_path(&y,&x);
     assert_eq!(xpath.len(),ypath.len());
     println!("{}",res);
     println!("{:?}\n{:?}",x,y);
@@ -80,7 +92,7 @@ fn test_dtw8(){
     let _ = normalize::NormalizeType::Z;
     let x = vec![1., 1., 3.,1.,1.];//,2., 2., 2., 2., 3., 2., 0.];
     let y = vec![2.,0., 3., 1.];//, 1., 2., 4., 2., 1., 2., 0.];
-    let (res,(ypath,xpath)) = dtw::subdtw_with_path(&y,&x);
+    let (res,(ypath,xpath)) = subdtw_with_path(&y,&x);
     assert_eq!(xpath.len(),ypath.len());
     println!("{}",res);
     println!("{:?}\n{:?}",x,y);
@@ -102,7 +114,7 @@ fn test_fastdtw_small(){
     let x:Vec<_> = (0..num).map(|i:usize| (2. * i as f32 * std::f32::consts::PI/ num as f32)).collect();
     let y:Vec<_>= (0..num).map(|i:usize| (2. * i as f32 * std::f32::consts::PI/ num as f32)).collect();
     let dist = |x:&f32,y:&f32| (x-y).powi(2);
-    let (score,_,_) = fast_dtw(&x,&y,&dist,radius,false).unwrap();
+    let score = fast_dtw(&x,&y,&dist,radius,false).unwrap().score;
     eprintln!("{}",score);
     debug_assert!(score<0.01,"{}",score);
 }
@@ -113,7 +125,7 @@ fn test_fastdtw_long(){
     let x:Vec<_> = (0..num).map(|i:usize| (2. * i as f32 * std::f32::consts::PI/ num as f32)).collect();
     let y:Vec<_>= (0..num).map(|i:usize| (2. * i as f32 * std::f32::consts::PI/ num as f32)).collect();
     let dist = |x:&f32,y:&f32| (x-y).powi(2);
-    let (score,_,_) = fast_dtw(&x,&y,&dist,radius,false).unwrap();
+    let score = fast_dtw(&x,&y,&dist,radius,false).unwrap().score;
     eprintln!("{}",score);
     debug_assert!(score<0.01,"{}",score);
 }
@@ -125,7 +137,7 @@ fn test_fastdtw_long_not_powerof2(){
     let x:Vec<_> = (0..num).map(|i:usize| (2. * i as f32 * std::f32::consts::PI/ num as f32)).collect();
     let y:Vec<_>= (0..num).map(|i:usize| (2. * i as f32 * std::f32::consts::PI/ num as f32)).collect();
     let dist = |x:&f32,y:&f32| (x-y).powi(2);
-    let (score,_,_) = fast_dtw(&x,&y,&dist,radius,false).unwrap();
+    let score = fast_dtw(&x,&y,&dist,radius,false).unwrap().score;
     eprintln!("{}",score);
     debug_assert!(score<0.01,"{}",score);
 }
@@ -141,7 +153,8 @@ fn test_fastdtw_sub_small(){
     }).collect();
     let x:Vec<_>= (0..num).map(|i:usize| (2. * i as f32 * std::f32::consts::PI/ num as f32)).collect();
     let dist = |x:&f32,y:&f32| (x-y).powi(2);
-    let (score,(x1path,x2path),_) = fast_dtw(&x,&y,&dist,radius,true).unwrap();
+    let Alignment{score,path,..} = fast_dtw(&x,&y,&dist,radius,true).unwrap();
+    let (x1path,x2path) = path.unwrap();
     for (&i,&j) in x1path.iter().zip(x2path.iter()){
         eprint!("({},{})->",i,j);
     }
@@ -161,11 +174,12 @@ fn test_fastdtw_sub_large(){
     }).collect();
     let x:Vec<_>= (0..num).map(|i:usize| (2. * i as f32 * std::f32::consts::PI/ num as f32).sin()).collect();
     eprintln!("{:?}",y);
-    let y1 = fastdtw::reduce_by_half(&y);
-    let x1 = fastdtw::reduce_by_half(&x);
+    let y1 = fastdtw::reduce_by(&y,2);
+    let x1 = fastdtw::reduce_by(&x,2);
     eprintln!("{:?}",y1);
     let dist = |x:&f32,y:&f32| (x-y).powi(2);
-    let (score,(x1path,x2path),_) = fast_dtw(&x1,&y1,&dist,radius,true).unwrap();
+    let Alignment{score,path,..} = fast_dtw(&x1,&y1,&dist,radius,true).unwrap();
+    let (x1path,x2path) = path.unwrap();
     for (&i,&j) in x1path.iter().zip(x2path.iter()){
         eprint!("({},{})->",i,j);
     }
@@ -184,16 +198,17 @@ fn test_fastdtw_sub_large_not_pow2(){
     }).collect();
     let x:Vec<_>= (0..num).map(|i:usize| (2. * i as f32 * std::f32::consts::PI/ num as f32).sin()).collect();
     eprintln!("{:?}",y);
-    let y1 = fastdtw::reduce_by_half(&y);
-    let x1 = fastdtw::reduce_by_half(&x);
+    let y1 = fastdtw::reduce_by(&y,2);
+    let x1 = fastdtw::reduce_by(&x,2);
     eprintln!("{:?}",y1);
     let dist = |x:&f32,y:&f32| (x-y).powi(2);
-    let (_,(x1path,x2path),_) = fast_dtw(&x1,&y1,&dist,radius,true).unwrap();
+    let Alignment{path,..} = fast_dtw(&x1,&y1,&dist,radius,true).unwrap();
+    let (x1path,x2path) = path.unwrap();
     for (&i,&j) in x1path.iter().zip(x2path.iter()){
         eprint!("({},{})->",i,j);
     }
     eprintln!("finish");
-    let (score,_,_) = fast_dtw(&x,&y,&dist,radius,true).unwrap();
+    let score = fast_dtw(&x,&y,&dist,radius,true).unwrap().score;
     debug_assert!(score<0.01,"{}",score);
 }
 #[test]
@@ -206,7 +221,7 @@ fn test_how_good(){
     let dist = |x:&f32,y:&f32| (x-y).powi(2);
     let x :Vec<f32> =  rng.gen_iter().take(num).collect();
     let y :Vec<f32> = rng.gen_iter().take(num).collect();
-    let (score,_,_) = fast_dtw(&x,&y,&dist,radius,true).unwrap();
+    let score = fast_dtw(&x,&y,&dist,radius,true).unwrap().score;
     assert!(score != 0.);
 }
 #[test]
@@ -219,8 +234,8 @@ fn test_how_good2(){
     let dist = |x:&f32,y:&f32| (x-y).powi(2);
     let x :Vec<f32> =  rng.gen_iter().take(num).collect();
     let y :Vec<f32> = rng.gen_iter().take(num).collect();
-    let (score,_,_) = fast_dtw(&x,&y,&dist,radius,false).unwrap();
-    let (optscore,_,_) = dtw::dtw(&x,&y,dtw::Mode::Full,&dist).unwrap();
+    let score = fast_dtw(&x,&y,&dist,radius,false).unwrap().score;
+    let optscore = dtw(&x,&y,Mode::Full,&dist).unwrap().score;
     debug_assert!((score-optscore)/optscore < 0.05,"{},{}",score,optscore);
 }
 
@@ -231,8 +246,8 @@ fn test_how_good2(){
 //     let dist = |x:&f32,y:&f32| (x-y).powi(2);
 //     let x :Vec<f32> =  rng.gen_iter().take(query).collect();
 //     let y :Vec<f32> = rng.gen_iter().take(reference).collect();
-//     let (score,_,_) = fast_dtw(&x,&y,&dist,radius,true).unwrap();
-//     let (optscore,_,_) = dtw::dtw(&x,&y,dtw::Mode::Sub,&dist).unwrap();
+//     let score = fast_dtw(&x,&y,&dist,radius,true).unwrap().score;
+//     let optscore = dtw(&x,&y,Mode::Sub,&dist).unwrap().score;
 //     eprintln!("(r:{},q:{}r:{}){}",reference,query,radius,(score-optscore)/optscore);
 //     assert!(true);
 // }
@@ -260,8 +275,8 @@ fn quick(){
     reference.append(&mut noise(2000));
     for _ in 0..10{
         let query = noisy_courve(200);
-        if let Ok((res,_,_)) = super::dtw::dtw(&query,&reference,super::dtw::Mode::Sub,&hill){
-            if let Ok((res2,_,_)) = super::dtw::dtw(&query,&reference,super::dtw::Mode::QuickSub,&hill){
+        if let Ok(Alignment{score:res,..}) = super::dtw(&query,&reference,super::Mode::Sub,&hill){
+            if let Ok(Alignment{score:res2,..}) = super::dtw(&query,&reference,super::Mode::QuickSub,&hill){
                 assert!((res-res2)<0.1,"{},{}",res,res2)
             }else{
                 assert!(false)
@@ -284,6 +299,17 @@ fn noise(t:usize)->Vec<f32>{
     (0..t).map(|_| normal.ind_sample(&mut rng) as f32).collect()
 }
 
+// A generator with a fixed seed, so that a failure can be reproduced from the seed.
+fn seeded(seed:usize)->rand::StdRng{
+    use rand::SeedableRng;
+    rand::StdRng::from_seed(&[seed][..])
+}
+fn noise_with<R:rand::Rng>(rng:&mut R,t:usize)->Vec<f32>{
+    use rand::distributions::{IndependentSample,Normal};
+    let normal = Normal::new(0.,1.,);
+    (0..t).map(|_| normal.ind_sample(rng) as f32).collect()
+}
+
 fn get_score(querysize:usize,refsize:usize,
              radius:usize,n:i32,alpha:f32)->Option<f64>{
     let hill = move |x:&f32,y:&f32|{
@@ -293,7 +319,7 @@ fn get_score(querysize:usize,refsize:usize,
     let query = sin_curve(querysize);
     let reference = noise(refsize);
     fast_dtw(&query,&reference,&hill,radius,true)
-        .map(|e|e.0 as f64).ok()
+        .map(|e|e.score as f64).ok()
 }
 
 #[test]
@@ -322,7 +348,7 @@ fn read_eve(path:&std::path::Path)->Result<(Vec<f32>,String),()>{
         None => return Err(()),
     };
     let f = File::open(path).map_err(|_|())?;
-    let query = &BufReader::new(f)
+    let query: Vec<f32> = BufReader::new(f)
         .lines().skip(1).filter_map(|e|e.ok())
         .filter_map(|line|line.split(',').nth(3)
                     .and_then(|e| e.parse().ok()))
@@ -347,10 +373,10 @@ fn signal_test(){
         .collect();
     for &(ref q1,_) in queries.iter(){
         for &(ref q2,_) in queries.iter(){
-            let fast = dtw::dtw(q1,q2,dtw::Mode::FastSub(50),&hill).unwrap();
-            let full = dtw::dtw(q1,q2,dtw::Mode::Sub,&hill).unwrap();
-            assert!(fast.0 == 0. || (fast.0-full.0)/full.0*100. < 10.
-                    ,"{}\t{}\t{}",fast.0,full.0,(fast.0-full.0)/full.0*100.);
+            let fast = dtw(q1,q2,Mode::FastSub(50),&hill).unwrap();
+            let full = dtw(q1,q2,Mode::Sub,&hill).unwrap();
+            assert!(fast.score == 0. || (fast.score-full.score)/full.score*100. < 10.
+                    ,"{}\t{}\t{}",fast.score,full.score,(fast.score-full.score)/full.score*100.);
         }
     }
 }
@@ -371,8 +397,8 @@ fn mock(){
     let y:Vec<_> = (0..1000).map(|_| normal.ind_sample(&mut rng) as f32).collect();
     for _ in 0..9{
         let x:Vec<_> = (0..500).map(|_| normal.ind_sample(&mut rng) as f32).collect();
-        let (score,_,_) = fast_dtw(&x,&y,&hill,radius,true).unwrap();
-        let (optscore,_,_) = dtw::dtw(&x,&y,dtw::Mode::Sub,&hill).unwrap();
+        let score = fast_dtw(&x,&y,&hill,radius,true).unwrap().score;
+        let optscore = dtw(&x,&y,Mode::Sub,&hill).unwrap().score;
         eprintln!("{}\t{}\t{}",score,optscore,(score-optscore)/optscore);
     }
     assert!(true);
@@ -386,6 +412,12 @@ fn noisy_courve(t:usize)->Vec<f32>{
     sin_curve(t).into_iter()
         .map(|e| e + normal.ind_sample(&mut rng) as f32).collect()
 }
+fn noisy_courve_with<R:rand::Rng>(rng:&mut R,t:usize)->Vec<f32>{
+    use rand::distributions::{Normal,IndependentSample};
+    let normal = Normal::new(0.,0.5,);
+    sin_curve(t).into_iter()
+        .map(|e| e + normal.ind_sample(rng) as f32).collect()
+}
 
 #[test]
 fn noisy(){
@@ -402,8 +434,8 @@ fn noisy(){
             .chain((0..450).map(|_|normal.ind_sample(&mut rng2)as f32))
             .collect();
         let x:Vec<_> = noisy_courve(180);
-        let (score,_,l1) = fast_dtw(&x,&y,&hill,radius,true).unwrap();
-        let (optscore,_,l2) = dtw::dtw(&x,&y,dtw::Mode::Sub,&hill).unwrap();
+        let Alignment{score:score,reference_span:l1,..} = fast_dtw(&x,&y,&hill,radius,true).unwrap();
+        let Alignment{score:optscore,reference_span:l2,..} = dtw(&x,&y,Mode::Sub,&hill).unwrap();
         for p in y {
             eprintln!("{}",p);
         }
@@ -412,7 +444,7 @@ fn noisy(){
             eprintln!("{}",p);
         }
         // assert!((score-optscore)/optscore<0.05,"{}\t{}\t{}\t:{}\t{}",score,optscore,(score-optscore)/optscore,l1,l2);
-        eprintln!("{}\t{}\t{}\t:{}\t{}",score,optscore,(score-optscore)/optscore,l1,l2);
+        eprintln!("{}\t{}\t{}\t:{:?}\t{:?}",score,optscore,(score-optscore)/optscore,l1,l2);
     }
 }
 
@@ -430,9 +462,9 @@ fn should_false(){
     let mut reference = vec![0.;referencelen/2-querylen/2];
     reference.append(&mut sin_curve(querylen));
     reference.append(&mut vec![0.;referencelen/2-querylen/2]);
-    let (score,_,l1) = fast_dtw(&query,&reference,&hill,radius,true).unwrap();
-    let (optscore,_,l2) = dtw::dtw(&query,&reference,dtw::Mode::Sub,&hill).unwrap();
-    assert!(true,"{} from {} vs {} from {}",score,l1,optscore,l2);
+    let Alignment{score:score,reference_span:l1,..} = fast_dtw(&query,&reference,&hill,radius,true).unwrap();
+    let Alignment{score:optscore,reference_span:l2,..} = dtw(&query,&reference,Mode::Sub,&hill).unwrap();
+    assert!(true,"{} from {:?} vs {} from {:?}",score,l1,optscore,l2);
 }
 
 #[test]
@@ -451,8 +483,10 @@ fn test_how_good6(){
     let y :Vec<f32> = noise.iter().chain(xs.iter()).map(|&e|e).collect();
     rng.shuffle(&mut noise);
     let y :Vec<f32>= y.into_iter().chain(noise.into_iter()).collect();
-    let (score,(x1,y1),_) = fast_dtw(&x,&y,&dist,radius,true).unwrap();
-    let (optscore,(x2,y2),_) = dtw::dtw(&x,&y,dtw::Mode::Sub,&dist).unwrap();
+    let Alignment{score,path,..} = fast_dtw(&x,&y,&dist,radius,true).unwrap();
+    let (x1,y1) = path.unwrap();
+    let Alignment{score:optscore,path,..} = dtw(&x,&y,Mode::Sub,&dist).unwrap();
+    let (x2,y2) = path.unwrap();
     eprintln!("fast:{}",score);
     for (i,j) in x1.into_iter().zip(y1.into_iter()){
         eprint!("({},{})->",i,j);
@@ -482,7 +516,7 @@ fn mini_ucr(){
     let dtw = ucr_dtw::DynamicTimeWarping::new(reference.clone(),hill,bandwidth);
     let query = noise(100);
     let (tes,_) = dtw.dtw(&query);
-    let (score,_,_) = super::dtw::dtw(&query,&reference,super::dtw::Mode::Full,&hill).unwrap();
+    let score = super::dtw(&query,&reference,super::Mode::Full,&hill).unwrap().score;
     assert!((score-tes).abs() < 0.01,"{},{}",score,tes);
 }
 
@@ -493,7 +527,7 @@ fn sin_ucr(){
     let dtw = ucr_dtw::DynamicTimeWarping::new(reference.clone(),hill,bandwidth);
     let query = sin_curve(100);
     let (tes,_) = dtw.dtw(&query);
-    let (score,_,_) = super::dtw::dtw(&query,&reference,super::dtw::Mode::Sub,&hill).unwrap();
+    let score = super::dtw(&query,&reference,super::Mode::Sub,&hill).unwrap().score;
     assert!((score-tes).abs() < 0.01,"{},{}",score,tes);
 }
 
@@ -504,7 +538,7 @@ fn chiba_ucr(){
     let dtw = ucr_dtw::DynamicTimeWarping::new(reference.clone(),hill,bandwidth);
     let query = sin_curve(100);
     let (tes,_) = dtw.dtw(&query);
-    let (score,_,_) = super::dtw::dtw(&query,&reference,super::dtw::Mode::SakoeChiba(9),&hill).unwrap();
+    let score = super::dtw(&query,&reference,super::Mode::SakoeChiba(9),&hill).unwrap().score;
     assert!((score-tes).abs() < 0.01,"{},{}",score,tes);
 }
 #[test]
@@ -516,8 +550,238 @@ fn exec_ucr(){
     let dtw = ucr_dtw::DynamicTimeWarping::new(reference.clone(),hill,bandwidth);
     let query = sin_curve(200);
     let (score,pos) = dtw.dtw(&query);
-    let (tes,_,position) = super::dtw::dtw(&query,&reference,super::dtw::Mode::Sub,&hill).unwrap();
-    assert!((score-tes).abs() < 0.01,"{},{},{},{}",score,tes,pos,position);
+    let Alignment{score:tes,reference_span:position,..} = super::dtw(&query,&reference,super::Mode::Sub,&hill).unwrap();
+    assert!((score-tes).abs() < 0.01,"{},{},{},{:?}",score,tes,pos,position);
+}
+
+#[inline]
+fn normal(x:&f32,y:&f32)->f32{
+    (x-y).powi(2)
+}
+
+fn stats_sum(stats:&PruningStats)->usize{
+    stats.kim + stats.keogh_query + stats.keogh_reference + stats.abandoned + stats.completed
+}
+
+#[test]
+fn ucr_vs_windows(){
+    let reference = noise(2000);
+    let query = noise(64);
+    let bandwidth = 5;
+    let dtw = ucr_dtw::DynamicTimeWarping::new(reference.clone(),hill,bandwidth);
+    let (score,pos,stats) = dtw.dtw_with_stats(&query);
+    let (opt,opt_pos) = reference.windows(64).enumerate()
+        .map(|(s,w)|(super::dtw(&query,w,Mode::SakoeChiba(2*bandwidth+1),&hill).unwrap().score,s))
+        .fold((std::f32::INFINITY,0),|acc,x| if x.0 < acc.0 {x} else {acc});
+    assert!((score-opt).abs() < 0.001,"{},{}",score,opt);
+    assert_eq!(pos,opt_pos);
+    assert_eq!(stats.windows,2000-64+1);
+    assert_eq!(stats_sum(&stats),stats.windows);
+    assert!(stats.completed < stats.windows);
+}
+
+#[test]
+fn ucr_normalized_vs_windows(){
+    let reference = noise(1000);
+    let query = noisy_courve(50);
+    let bandwidth = 4;
+    let dtw = ucr_dtw::DynamicTimeWarping::new(reference.clone(),normal,bandwidth);
+    let (score,pos,stats) = dtw.normalized_dtw(&query);
+    let znorm = |xs:&[f32]|{
+        let mean = xs.iter().sum::<f32>()/xs.len() as f32;
+        let std = (xs.iter().map(|x|(x-mean).powi(2)).sum::<f32>()/xs.len() as f32).sqrt();
+        xs.iter().map(|x|(x-mean)/std).collect::<Vec<_>>()
+    };
+    let q = znorm(&query);
+    let (opt,opt_pos) = reference.windows(50).enumerate()
+        .map(|(s,w)|(super::dtw(&q,&znorm(w),Mode::SakoeChiba(2*bandwidth+1),&normal).unwrap().score,s))
+        .fold((std::f32::INFINITY,0),|acc,x| if x.0 < acc.0 {x} else {acc});
+    assert!((score-opt).abs() < 0.01 * opt,"{},{}",score,opt);
+    assert_eq!(pos,opt_pos);
+    assert_eq!(stats_sum(&stats),stats.windows);
+}
+
+// Greedy top-k windows with exclusion from the scores of all the windows.
+fn top_k_windows(scores:&[f32],k:usize,exclusion:usize)->Vec<(f32,usize)>{
+    let mut windows:Vec<_> = scores.iter().cloned().zip(0..).collect();
+    windows.sort_by(|a,b|a.partial_cmp(b).unwrap());
+    let mut taken:Vec<(f32,usize)> = vec![];
+    for (score,start) in windows{
+        if taken.len() < k && taken.iter().all(|&(_,s)| s + exclusion <= start || start + exclusion <= s){
+            taken.push((score,start));
+        }
+    }
+    taken
+}
+
+#[test]
+fn ucr_top_k_vs_windows(){
+    let reference = noise(1500);
+    let query = noise(40);
+    let bandwidth = 3;
+    let dtw = ucr_dtw::DynamicTimeWarping::new(reference.clone(),hill,bandwidth);
+    let scores:Vec<_> = reference.windows(40)
+        .map(|w|super::dtw(&query,w,Mode::SakoeChiba(2*bandwidth+1),&hill).unwrap().score)
+        .collect();
+    for &(k,exclusion) in &[(1,1),(3,20),(5,40)]{
+        let (hits,stats) = dtw.top_k(&query,k,exclusion);
+        let answer = top_k_windows(&scores,k,exclusion);
+        assert_eq!(hits.len(),k);
+        assert_eq!(stats_sum(&stats),stats.windows);
+        for (hit,&(score,start)) in hits.iter().zip(answer.iter()){
+            assert!((hit.score-score).abs() < 0.001,"{},{}",hit.score,score);
+            assert_eq!(hit.reference_span,Some((start,start+40)));
+            let (xpath,ypath) = hit.path.clone().unwrap();
+            let sum:f32 = xpath.iter().zip(ypath.iter()).map(|(&i,&j)|hill(&query[i],&reference[j])).sum();
+            assert!((hit.score-sum).abs() < 0.001);
+            assert!(xpath.iter().zip(ypath.iter()).all(|(&i,&j)| (i + start).max(j) - (i + start).min(j) <= bandwidth));
+        }
+    }
+    let (score,pos) = dtw.dtw(&query);
+    let (hits,_) = dtw.top_k(&query,1,1);
+    assert_eq!((hits[0].score,hits[0].reference_span),(score,Some((pos,pos+40))));
+}
+
+#[test]
+fn ucr_top_k_copies(){
+    let query = sin_curve(100);
+    let mut reference = noise(1000);
+    for &(scale,shift) in &[(2.,1.),(0.5,-3.),(4.,20.)]{
+        reference.extend(query.iter().map(|x| scale * x + shift));
+        reference.extend(noise(1000));
+    }
+    let dtw = ucr_dtw::DynamicTimeWarping::new(reference,normal,5);
+    let (hits,stats) = dtw.normalized_top_k(&query,3,100);
+    let mut spans:Vec<_> = hits.iter().map(|h|h.reference_span.unwrap()).collect();
+    spans.sort();
+    assert_eq!(spans,vec![(1000,1100),(2100,2200),(3200,3300)]);
+    assert!(hits.iter().all(|h|h.score < 0.001 && h.has_path()));
+    assert!(stats.completed < stats.windows);
+    let (hits,_) = dtw.top_k(&query,0,100);
+    assert!(hits.is_empty());
+}
+
+// A path in the temporary directory unique to this process and name.
+fn temp_index(name:&str)->std::path::PathBuf{
+    std::env::temp_dir().join(format!("dtw_{}_{}.idx",std::process::id(),name))
+}
+
+#[test]
+fn index_roundtrip(){
+    let mut reference = noise(3000);
+    reference.extend(sin_curve(100).iter().map(|x| 2. * x + 1.));
+    reference.extend(noise(1001));
+    let query = sin_curve(100);
+    let path = temp_index("roundtrip");
+    let dtw = ucr_dtw::DynamicTimeWarping::new(reference.clone(),normal,7);
+    dtw.save(&path).unwrap();
+    let loaded = ucr_dtw::DynamicTimeWarping::load(&path,normal,7).unwrap();
+    assert_eq!(loaded.bandwidth(),7);
+    assert_eq!(dtw.dtw_with_stats(&query),loaded.dtw_with_stats(&query));
+    assert_eq!(dtw.normalized_dtw(&query),loaded.normalized_dtw(&query));
+    assert_eq!(loaded.normalized_dtw(&query).1,3000);
+    let (hits,_) = dtw.top_k(&query,3,100);
+    let (hits2,_) = loaded.top_k(&query,3,100);
+    assert_eq!(hits,hits2);
+    // f64 reference.
+    let reference:Vec<f64> = reference.iter().map(|&x| x as f64).collect();
+    let dist = |x:&f64,y:&f64| (x-y).powi(2);
+    let dtw = ucr_dtw::DynamicTimeWarping::new(reference,dist,7);
+    dtw.save(&path).unwrap();
+    let loaded = ucr_dtw::DynamicTimeWarping::load(&path,dist,7).unwrap();
+    let query:Vec<f64> = query.iter().map(|&x| x as f64).collect();
+    assert_eq!(dtw.dtw_with_stats(&query),loaded.dtw_with_stats(&query));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn index_mismatch(){
+    let path = temp_index("mismatch");
+    let dtw = ucr_dtw::DynamicTimeWarping::new(noise(100),normal,5);
+    dtw.save(&path).unwrap();
+    match ucr_dtw::DynamicTimeWarping::load(&path,normal,6){
+        Err(IndexError::BandwidthMismatch{stored:5,requested:6}) => {},
+        x => panic!("{:?}",x.map(|_|())),
+    }
+    let dist = |x:&f64,y:&f64| (x-y).powi(2);
+    match ucr_dtw::DynamicTimeWarping::load(&path,dist,5){
+        Err(IndexError::ElementMismatch{expected:8,found:4}) => {},
+        x => panic!("{:?}",x.map(|_|())),
+    }
+    // Truncated file.
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::write(&path,&bytes[..bytes.len()-8]).unwrap();
+    match ucr_dtw::DynamicTimeWarping::load(&path,normal,5){
+        Err(IndexError::InvalidFormat) => {},
+        x => panic!("{:?}",x.map(|_|())),
+    }
+    std::fs::write(&path,b"not an index").unwrap();
+    match ucr_dtw::DynamicTimeWarping::load(&path,normal,5){
+        Err(IndexError::InvalidFormat) => {},
+        x => panic!("{:?}",x.map(|_|())),
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert!(ucr_dtw::DynamicTimeWarping::load(&path,normal,5).is_err());
+}
+
+#[test]
+fn index_corrupted_length(){
+    let path = temp_index("corrupted");
+    let dtw = ucr_dtw::DynamicTimeWarping::new(noise(100),normal,5);
+    dtw.save(&path).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    // The length in the header is larger than the file.
+    let mut truncated = bytes[..bytes.len()/2].to_vec();
+    truncated[24..32].copy_from_slice(&100u64.to_le_bytes());
+    // The sizes of the sections overflow.
+    let mut overflow = bytes.clone();
+    overflow[24..32].copy_from_slice(&(u64::MAX/4 + 1).to_le_bytes());
+    let mut overflow_total = bytes.clone();
+    overflow_total[24..32].copy_from_slice(&(u64::MAX/8).to_le_bytes());
+    for bytes in &[truncated,overflow,overflow_total]{
+        std::fs::write(&path,bytes).unwrap();
+        match ucr_dtw::DynamicTimeWarping::load(&path,normal,5){
+            Err(IndexError::InvalidFormat) => {},
+            x => panic!("{:?}",x.map(|_|())),
+        }
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn ucr_batch(){
+    fn is_sync<T:Sync>(_:&T){}
+    let reference = noise(3000);
+    let dtw = ucr_dtw::DynamicTimeWarping::new(reference,normal,5);
+    is_sync(&dtw);
+    let queries:Vec<_> = (0..20).map(|i| noise(30 + i)).collect();
+    let answer:Vec<_> = queries.iter().map(|q| dtw.dtw(q)).collect();
+    for &threads in &[0,1,3,100]{
+        assert_eq!(dtw.batch_dtw(&queries,threads),answer);
+    }
+    let hits = dtw.batch(&queries,2,|dtw,q| dtw.top_k(q,2,q.len()).0);
+    for (hit,q) in hits.iter().zip(queries.iter()){
+        assert_eq!(hit,&dtw.top_k(q,2,q.len()).0);
+    }
+    let empty:Vec<Vec<f32>> = vec![];
+    assert!(dtw.batch_dtw(&empty,4).is_empty());
+}
+
+#[test]
+fn ucr_normalized_finds_scaled_copy(){
+    let query = sin_curve(100);
+    let mut reference = noise(3000);
+    reference.extend(query.iter().map(|x| 3. * x + 10.));
+    reference.extend(noise(3000));
+    let dtw = ucr_dtw::DynamicTimeWarping::new(reference,normal,10);
+    let (score,pos,stats) = dtw.normalized_dtw(&query);
+    assert!(score < 0.001,"{}",score);
+    assert_eq!(pos,3000);
+    assert_eq!(stats_sum(&stats),stats.windows);
+    let (score,pos,stats) = dtw.normalized_euclidean(&query);
+    assert!(score < 0.001,"{}",score);
+    assert_eq!(pos,3000);
+    assert_eq!(stats.abandoned + stats.completed,stats.windows);
 }
 
 
@@ -526,9 +790,9 @@ fn threshold(){
     let reference = noise(1000);
     for _ in 0..10 {
         let query = noise(100);
-        let score = dtw::dtw(&query,&reference,dtw::Mode::QuickSub,&hill).unwrap().0;
-        let score2 = dtw::dtw(&query,&reference,dtw::Mode::Sub,&hill).unwrap().0;
-        let thre = thresholddtw(&query,&reference,&hill,100000.).unwrap().0;
+        let score = dtw(&query,&reference,Mode::QuickSub,&hill).unwrap().score;
+        let score2 = dtw(&query,&reference,Mode::Sub,&hill).unwrap().score;
+        let thre = thresholddtw(&query,&reference,&hill,100000.).unwrap().score;
         assert!((score-thre).abs()<0.01,"{},{},{}",score,thre,score2);
     }
 }
@@ -537,8 +801,8 @@ fn threshold(){
 fn threshold_small(){
     let reference = vec![1., 1., 1., 2., 2., 2., 2.];
     let query = vec![1., 1., 2.,2.];
-    let score = dtw::dtw(&query,&reference,dtw::Mode::Sub,&hill).unwrap().0;
-    let thre = thresholddtw(&query,&reference,&hill,100000.).unwrap().0;
+    let score = dtw(&query,&reference,Mode::Sub,&hill).unwrap().score;
+    let thre = thresholddtw(&query,&reference,&hill,100000.).unwrap().score;
     assert!((score-thre).abs()<0.01,"{},{}",score,thre);
 }
 
@@ -549,9 +813,9 @@ fn scouting_small(){
         .chain(noisy_courve(500).into_iter())
         .chain(noise(1000).into_iter())
         .collect();
-    let score = dtw::dtw(&query,&reference,dtw::Mode::Sub,&hill).unwrap();
+    let score = dtw(&query,&reference,Mode::Sub,&hill).unwrap();
     let scout = scouting_dtw(&query,&reference,&hill,Some(3),Some(1)).unwrap();
-    assert!((score.0-scout.0).abs() < 0.01,"{},{}",score.0,scout.0);
+    assert!((score.score-scout.score).abs() < 0.01,"{},{}",score.score,scout.score);
 }
 
 #[test]
@@ -563,9 +827,9 @@ fn scouting_manytimes(){
             .chain(noisy_courve(500).into_iter())
             .chain(noise(1000).into_iter())
             .collect();
-        let score = dtw::dtw(&query,&reference,dtw::Mode::Sub,&hill).unwrap();
+        let score = dtw(&query,&reference,Mode::Sub,&hill).unwrap();
         let scout = scouting_dtw(&query,&reference,&hill,None,None).unwrap();
-        (score.0 -scout.0).abs()/score.0})
+        (score.score -scout.score).abs()/score.score})
         .sum();
     // assertion pass when overall error rate is less than 0.2 percent.
     assert!(sum <= 0.2,"average error:{}",sum *100./ times as f32);
@@ -574,8 +838,8 @@ fn scouting_manytimes(){
 fn scouting_when_maxpack(){
     let reference:Vec<_> = noise(500);
     let query = noise(500);
-    let sub = dtw::dtw(&query,&reference,dtw::Mode::Sub,&hill).unwrap().0;
-    let scout = scouting_dtw(&query,&reference,&hill,Some(1),Some(1)).unwrap().0;
+    let sub = dtw(&query,&reference,Mode::Sub,&hill).unwrap().score;
+    let scout = scouting_dtw(&query,&reference,&hill,Some(1),Some(1)).unwrap().score;
     assert!(sub == scout,"{},{}",sub,scout);
 }
 
@@ -588,9 +852,684 @@ fn scouting_maxpack_manytimes(){
             .chain(noisy_courve(500).into_iter())
             .chain(noise(1000).into_iter())
             .collect();
-        let score = dtw::dtw(&query,&reference,dtw::Mode::Sub,&hill).unwrap();
+        let score = dtw(&query,&reference,Mode::Sub,&hill).unwrap();
         let scout = scouting_dtw(&query,&reference,&hill,Some(1),Some(1)).unwrap();
-        (score.0 -scout.0).abs()/score.0})
+        (score.score -scout.score).abs()/score.score})
         .sum();
     assert!(sum <= 0.01,"average error:{}",sum *100./ times as f32);
 }
+
+// Check whether the path stays inside the Itakura parallelogram with slope 2.
+fn in_itakura(xpath:&[usize],ypath:&[usize],x1len:usize,x2len:usize)->bool{
+    let (m,n) = ((x1len-1) as f32,(x2len-1) as f32);
+    let slope = n/m;
+    xpath.iter().zip(ypath.iter()).all(|(&i,&j)|{
+        let (i,j) = (i as f32,j as f32);
+        slope/2.*i <= j + 0.001 && j <= 2.*slope*i + 0.001 &&
+            n - 2.*slope*(m-i) <= j + 0.001 && j <= n - slope/2.*(m-i) + 0.001
+    })
+}
+
+#[test]
+fn itakura_vs_full(){
+    // Noise makes the optimal path of Mode::Full leave the parallelogram.
+    for (x1,x2) in [(noisy_courve(200),noisy_courve(200)),(noise(200),noise(200))]{
+        let dist = |x:&f32,y:&f32| (x-y).powi(2);
+        let Alignment{score:full,path,..} = dtw(&x1,&x2,Mode::Full,&dist).unwrap();
+        let (xpath,ypath) = path.unwrap();
+        let aln = dtw(&x1,&x2,Mode::Itakura(401),&dist).unwrap();
+        assert_eq!(aln.reference_span,Some((0,200)));
+        let Alignment{score:itakura,path,..} = aln;
+        let (xpath2,ypath2) = path.unwrap();
+        assert!(in_itakura(&xpath2,&ypath2,x1.len(),x2.len()));
+        assert!(itakura >= full - 0.001,"{},{}",itakura,full);
+        if in_itakura(&xpath,&ypath,x1.len(),x2.len()){
+            assert!((full-itakura).abs()<0.001,"{},{}",full,itakura);
+            assert_eq!(xpath,xpath2);
+            assert_eq!(ypath,ypath2);
+        }
+    }
+}
+
+#[test]
+fn itakura_vs_full_unequal(){
+    let x1 = sin_curve(100);
+    let x2 = sin_curve(130);
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let Alignment{score:full,path,..} = dtw(&x1,&x2,Mode::Full,&dist).unwrap();
+    let (xpath,ypath) = path.unwrap();
+    assert!(in_itakura(&xpath,&ypath,x1.len(),x2.len()));
+    let Alignment{score:itakura,path,..} = dtw(&x1,&x2,Mode::Itakura(261),&dist).unwrap();
+    let (xpath2,ypath2) = path.unwrap();
+    assert!((full-itakura).abs()<0.001,"{},{}",full,itakura);
+    assert_eq!(xpath,xpath2);
+    assert_eq!(ypath,ypath2);
+    let itakura = dtw(&x2,&x1,Mode::Itakura(261),&dist).unwrap().score;
+    let full = dtw(&x2,&x1,Mode::Full,&dist).unwrap().score;
+    assert!((full-itakura).abs()<0.001,"{},{}",full,itakura);
+}
+
+#[test]
+fn itakura_narrow_band(){
+    let x1 = noise(150);
+    let x2 = noise(100);
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let full = dtw(&x1,&x2,Mode::Full,&dist).unwrap().score;
+    let wide = dtw(&x1,&x2,Mode::Itakura(301),&dist).unwrap().score;
+    let narrow = dtw(&x1,&x2,Mode::Itakura(5),&dist).unwrap().score;
+    assert!(full <= wide && wide <= narrow,"{},{},{}",full,wide,narrow);
+}
+
+#[test]
+fn chiba_vs_full_unequal(){
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    for &(x1len,x2len) in &[(100,130),(130,100),(100,140)]{
+        let x1 = noisy_courve(x1len);
+        let x2 = noisy_courve(x2len);
+        // The band covers the whole matrix.
+        let band = 2*x1len.max(x2len)+1;
+        let full = dtw(&x1,&x2,Mode::Full,&dist).unwrap();
+        let chiba = dtw(&x1,&x2,Mode::SakoeChiba(band),&dist).unwrap();
+        assert!((full.score-chiba.score).abs()<0.001,"{},{}",full.score,chiba.score);
+        assert_eq!(full.path,chiba.path);
+        assert_eq!(chiba.reference_span,Some((0,x2len)));
+    }
+}
+
+#[test]
+fn chiba_narrow_band(){
+    let x1 = noise(150);
+    let x2 = noise(100);
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let full = dtw(&x1,&x2,Mode::Full,&dist).unwrap().score;
+    let wide = dtw(&x1,&x2,Mode::SakoeChiba(21),&dist).unwrap().score;
+    let narrow = dtw(&x1,&x2,Mode::SakoeChiba(5),&dist).unwrap().score;
+    assert!(full <= wide && wide <= narrow,"{},{},{}",full,wide,narrow);
+}
+
+#[test]
+fn sub_chiba_vs_windows(){
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let query = noisy_courve(50);
+    let reference = noise(400);
+    for &band in &[3,11,101]{
+        let (score,offset) = reference.windows(50).enumerate()
+            .map(|(s,w)|(dtw(&query,w,Mode::SakoeChiba(band),&dist).unwrap().score,s))
+            .fold((std::f32::INFINITY,0),|acc,x| if x.0 < acc.0 {x} else {acc});
+        let aln = dtw(&query,&reference,Mode::SubSakoeChiba(band),&dist).unwrap();
+        assert_eq!(aln.score,score);
+        assert_eq!(aln.reference_span,Some((offset,offset+50)));
+        let window = dtw(&query,&reference[offset..offset+50],Mode::SakoeChiba(band),&dist).unwrap();
+        let (xpath,ypath) = window.path.unwrap();
+        let ypath:Vec<_> = ypath.iter().map(|j|j+offset).collect();
+        assert_eq!(aln.path,Some((xpath,ypath)));
+    }
+}
+
+#[test]
+fn sub_chiba_finds_copy(){
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let query = sin_curve(100);
+    let mut reference = noise(300);
+    reference.extend(query.iter());
+    reference.extend(noise(300));
+    let aln = dtw(&query,&reference,Mode::SubSakoeChiba(11),&dist).unwrap();
+    assert_eq!(aln.score,0.);
+    assert_eq!(aln.reference_span,Some((300,400)));
+}
+
+#[test]
+fn chiba_optimal_pads_whole_reference(){
+    use super::utils::chiba_optimal_dtw;
+    let query = vec![1.;10];
+    let mut reference = vec![1.;10];
+    reference.extend(vec![100.;30]);
+    let score = chiba_optimal_dtw(&query,&reference,&Mode::SakoeChiba(3),false);
+    assert_eq!(score,Some(0.));
+    // No window of the query length, but the padded reference is aligned as a whole.
+    let score = chiba_optimal_dtw(&query,&[1.;5],&Mode::SakoeChiba(3),false);
+    assert_eq!(score,Some(0.));
+}
+
+#[test]
+fn error_variants(){
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let empty:Vec<f32> = vec![];
+    let x = noise(10);
+    assert_eq!(dtw(&empty,&x,Mode::Sub,&dist).unwrap_err(),DtwError::EmptyInput);
+    assert_eq!(dtw(&x,&empty,Mode::Fast(2),&dist).unwrap_err(),DtwError::EmptyInput);
+    assert!(dtw(&x,&noise(12),Mode::SakoeChiba(3),&dist).is_ok());
+    assert_eq!(dtw(&x,&noise(10),Mode::SakoeChiba(2),&dist).unwrap_err(),
+               DtwError::InvalidBand(2));
+    let e :Box<dyn std::error::Error> = Box::new(DtwError::NoCandidates);
+    assert!(!e.to_string().is_empty());
+}
+
+#[test]
+fn threshold_abandoned(){
+    let query = vec![10.;20];
+    let reference = vec![0.;100];
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    match thresholddtw(&query,&reference,&dist,150.){
+        Err(DtwError::Abandoned{row,total}) => assert_eq!((row,total),(2,20)),
+        x => panic!("{:?}",x),
+    }
+    match scouting_threshold_dtw(&query,&reference,&dist,Some(3),Some(2),150.){
+        Err(DtwError::Abandoned{row,total}) => assert_eq!((row,total),(2,10)),
+        x => panic!("{:?}",x),
+    }
+}
+
+#[test]
+fn alignment_flags(){
+    let reference = noise(300);
+    let query = noise(50);
+    let sub = dtw(&query,&reference,Mode::Sub,&hill).unwrap();
+    let (qpath,rpath) = sub.path.clone().unwrap();
+    assert!(sub.has_path() && sub.has_reference_span());
+    assert_eq!(sub.query_span,(0,50));
+    assert_eq!(sub.reference_span,Some((rpath[0],rpath[rpath.len()-1]+1)));
+    assert_eq!(qpath.len(),rpath.len());
+    let fast = dtw(&query,&reference,Mode::FastSub(10),&hill).unwrap();
+    assert!(fast.has_path() && fast.has_reference_span());
+    assert_eq!(fast.query_span,(0,50));
+    let quick = dtw(&query,&reference,Mode::QuickSub,&hill).unwrap();
+    assert!(!quick.has_path());
+    let nw = needleman_dtw(&query,&reference,&hill,1.).unwrap();
+    assert!(!nw.has_path());
+}
+
+#[test]
+fn location_of_sub(){
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    for _ in 0..10{
+        let reference = noise(500);
+        let query = noise(40);
+        let sub = dtw(&query,&reference,Mode::Sub,&dist).unwrap();
+        let quick = dtw(&query,&reference,Mode::QuickSub,&dist).unwrap();
+        let thre = thresholddtw(&query,&reference,&dist,100000.).unwrap();
+        assert!((sub.score-quick.score).abs()<0.001);
+        assert!((sub.score-thre.score).abs()<0.001);
+        assert_eq!(sub.reference_span,quick.reference_span);
+        assert_eq!(sub.reference_span,thre.reference_span);
+    }
+}
+
+#[test]
+fn linear_vs_full(){
+    let x1 = noisy_courve(200);
+    let x2 = noisy_courve(300);
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let full = dtw(&x1,&x2,Mode::Full,&dist).unwrap();
+    let linear = dtw(&x1,&x2,Mode::LinearFull,&dist).unwrap();
+    assert_eq!(full,linear);
+}
+
+#[test]
+fn linear_vs_sub(){
+    let x1 = noisy_courve(150);
+    let mut x2 = noise(200);
+    x2.extend(noisy_courve(170));
+    x2.extend(noise(200));
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let sub = dtw(&x1,&x2,Mode::Sub,&dist).unwrap();
+    let linear = dtw(&x1,&x2,Mode::LinearSub,&dist).unwrap();
+    assert_eq!(sub,linear);
+}
+
+#[test]
+fn linear_repeated(){
+    for _ in 0..20{
+        let x1 = noise(80);
+        let x2 = noise(120);
+        let dist = |x:&f32,y:&f32| (x-y).powi(2);
+        for &(plain,linear) in &[(Mode::Full,Mode::LinearFull),(Mode::Sub,Mode::LinearSub)]{
+            let res = dtw(&x1,&x2,plain,&dist).unwrap();
+            let res2 = dtw(&x1,&x2,linear,&dist).unwrap();
+            assert_eq!(res,res2,"{}",linear);
+        }
+    }
+}
+
+#[test]
+fn linear_ties(){
+    // Small integers make a lot of tied paths, which should be chosen in the same way.
+    let x1:Vec<f32> = (0..150).map(|i| (i * 7 % 5) as f32).collect();
+    let x2:Vec<f32> = (0..230).map(|i| (i * 3 % 4) as f32).collect();
+    let dist = |x:&f32,y:&f32| (x-y).abs();
+    for &(plain,linear) in &[(Mode::Full,Mode::LinearFull),(Mode::Sub,Mode::LinearSub)]{
+        let res = dtw(&x1,&x2,plain,&dist).unwrap();
+        let res2 = dtw(&x1,&x2,linear,&dist).unwrap();
+        assert_eq!(res,res2,"{}",linear);
+    }
+}
+
+#[test]
+fn top_k_repeated_reference(){
+    for seed in 0..5{
+        let mut rng = seeded(seed);
+        let query = noisy_courve_with(&mut rng,100);
+        let reference:Vec<_> = noise_with(&mut rng,300).into_iter()
+            .chain(noisy_courve_with(&mut rng,100).into_iter())
+            .chain(noise_with(&mut rng,300).into_iter())
+            .chain(noisy_courve_with(&mut rng,100).into_iter())
+            .chain(noise_with(&mut rng,300).into_iter())
+            .collect();
+        let best = dtw(&query,&reference,Mode::QuickSub,&hill).unwrap();
+        let hits = subdtw_top_k(&query,&reference,&hill,2,10).unwrap();
+        assert_eq!(hits[0],best,"seed:{}",seed);
+        let mut starts:Vec<_> = hits.iter().map(|h|h.reference_start().unwrap()).collect();
+        starts.sort();
+        assert!(starts[0] < 400 && starts[1] >= 700,"seed:{},{:?}",seed,starts);
+    }
+}
+
+#[test]
+fn spring_vs_quick(){
+    let query = noisy_courve(100);
+    let reference:Vec<_> = noise(300).into_iter()
+        .chain(noisy_courve(100).into_iter())
+        .chain(noise(300).into_iter())
+        .collect();
+    let best = dtw(&query,&reference,Mode::QuickSub,&hill).unwrap();
+    let mut matcher = SpringMatcher::new(query.clone(),hill,std::f32::INFINITY).unwrap();
+    let mut hits = vec![];
+    for chunk in reference.chunks(37){
+        hits.extend(matcher.extend(chunk));
+    }
+    hits.extend(matcher.finish());
+    let spring = hits.into_iter().min_by(|a,b|a.score.partial_cmp(&b.score).unwrap()).unwrap();
+    assert_eq!(spring,best);
+}
+
+#[test]
+fn multidim_single_channel(){
+    let x1 = noisy_courve(120);
+    let x2 = noisy_courve(150);
+    let rows1:Vec<_> = x1.iter().map(|&x|[x]).collect();
+    let rows2:Vec<_> = x2.iter().map(|&x|[x]).collect();
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    for &mode in &[Mode::Full,Mode::Sub,Mode::Fast(3),Mode::FastSub(3),Mode::Itakura(41),Mode::QuickSub,Mode::LinearSub]{
+        let res = dtw(&x1,&x2,mode,&dist).unwrap();
+        let dependent = dtw_dependent(&rows1,&rows2,mode,&squared_euclidean).unwrap();
+        let independent = dtw_independent(&rows1,&rows2,mode,&dist).unwrap();
+        assert_eq!(res,dependent,"{}",mode);
+        assert_eq!(res,independent.channels[0],"{}",mode);
+        assert_eq!(res.score,independent.score);
+    }
+}
+
+#[test]
+fn multidim_fast(){
+    let x1:Vec<_> = noisy_courve(300).into_iter().zip(sin_curve(300)).map(|(x,y)|vec![x,y,x*y]).collect();
+    let x2:Vec<_> = noisy_courve(300).into_iter().zip(sin_curve(300)).map(|(x,y)|vec![x,y,x*y]).collect();
+    let full = dtw_dependent(&x1,&x2,Mode::Full,&squared_euclidean).unwrap();
+    let fast = dtw_dependent(&x1,&x2,Mode::Fast(10),&squared_euclidean).unwrap();
+    assert!(full.score <= fast.score);
+    assert!(fast.score <= full.score * 1.1,"{},{}",fast.score,full.score);
+}
+
+#[test]
+fn symmetric1_pattern_is_default(){
+    let x1 = noisy_courve(80);
+    let mut x2 = noise(50);
+    x2.extend(noisy_courve(90));
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    for &(mode,is_sub) in &[(Mode::Full,false),(Mode::Sub,true)]{
+        let res = dtw(&x1,&x2,mode,&dist).unwrap();
+        let res2 = super::step_pattern::pattern_dtw(&x1,&x2,&dist,StepPattern::Symmetric1,is_sub).unwrap();
+        assert_eq!(res,res2);
+    }
+}
+
+#[test]
+fn step_pattern_fast(){
+    let x1 = noisy_courve(300);
+    let x2 = noisy_courve(350);
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    for &step in &[StepPattern::Symmetric2,StepPattern::Asymmetric,StepPattern::SymmetricP(1,1),StepPattern::AsymmetricP(1,2)]{
+        let full = dtw_with_step(&x1,&x2,Mode::Full,step,&dist).unwrap();
+        let fast = dtw_with_step(&x1,&x2,Mode::Fast(10),step,&dist).unwrap();
+        assert!(full.score <= fast.score + 0.001,"{}",step);
+        assert!(fast.score <= full.score * 1.2,"{},{},{}",step,fast.score,full.score);
+        let fast2 = fast_dtw_with_step(&x1,&x2,&dist,10,false,step).unwrap();
+        assert_eq!(fast,fast2);
+    }
+}
+
+#[test]
+fn step_pattern_normalized(){
+    // A shorter copy of the same curve should give a comparable normalized score.
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let reference = sin_curve(200);
+    let long = sin_curve(200);
+    let short:Vec<_> = sin_curve(200).into_iter().step_by(2).collect();
+    let step = StepPattern::Symmetric2;
+    let long = dtw_with_step(&long,&reference,Mode::Full,step,&dist).unwrap();
+    let short = dtw_with_step(&short,&reference,Mode::Full,step,&dist).unwrap();
+    assert!(long.normalized_score(step).unwrap() < 0.01);
+    assert!(short.normalized_score(step).unwrap() < 0.01);
+    assert_eq!(dtw_with_step(&reference,&reference,Mode::QuickSub,step,&dist).err(),
+               Some(DtwError::UnsupportedStepPattern));
+}
+
+#[test]
+fn normalized_wrapper(){
+    use super::utils::{dtw_wrapper,dtw_wrapper_normalized};
+    let reference:Vec<_> = noise(300).into_iter()
+        .chain(noisy_courve(400).into_iter())
+        .chain(noise(300).into_iter())
+        .collect();
+    let long = noisy_courve(400);
+    let short:Vec<_> = noisy_courve(400).into_iter().step_by(2).collect();
+    for &mode in &[Mode::Sub,Mode::QuickSub,Mode::FastSub(20)]{
+        let raw = dtw_wrapper(&long,&reference,&mode,"normal",&None,&None).unwrap();
+        let by_query = dtw_wrapper_normalized(&long,&reference,&mode,"normal",&None,&None,Normalization::QueryLength).unwrap();
+        assert!((raw/400. - by_query).abs() < 0.001,"{},{},{}",mode,raw,by_query);
+    }
+    // One normalized threshold works for both lengths.
+    for query in &[long,short]{
+        for &by in &[Normalization::QueryLength,Normalization::PathLength]{
+            let score = dtw_wrapper_normalized(query,&reference,&Mode::Sub,"normal",&None,&Some(1.),by);
+            assert!(score.unwrap() < 1.);
+        }
+    }
+}
+
+#[test]
+fn local_costs_locate_mismatch(){
+    let x1 = sin_curve(100);
+    let mut x2 = sin_curve(100);
+    for x in x2[40..50].iter_mut(){ *x += 3. }
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let aln = dtw(&x1,&x2,Mode::Full,&dist).unwrap();
+    let costs = aln.local_costs(&x1,&x2,&dist).unwrap();
+    let (_,ypath) = aln.path.clone().unwrap();
+    assert!((costs.iter().sum::<f32>() - aln.score).abs() < 0.001);
+    for (c,j) in costs.iter().zip(ypath){
+        assert_eq!(*c > 1.,(40..50).contains(&j));
+    }
+}
+
+#[test]
+fn fast_keeps_odd_tail(){
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let x = vec![0.,0.,1.,2.,3.,4.,5.];
+    let reduced = fastdtw::reduce_by(&x,2);
+    assert_eq!(reduced,vec![0.,1.5,3.5,5.]);
+    assert_eq!(fastdtw::reduce_by(&x,3),vec![1./3.,3.,5.]);
+    // The copy at the very end of an odd-length reference.
+    let query = sin_curve(101);
+    let mut reference = vec![10.;200];
+    reference.extend(query.iter());
+    for &factor in &[2,3,4]{
+        let options = FastDtwOptions::new(5).factor(factor);
+        let aln = fast_dtw_with_options(&query,&reference,&dist,true,&options).unwrap();
+        assert_eq!(aln.score,0.,"{}",factor);
+        assert_eq!(aln.reference_span,Some((200,301)));
+    }
+}
+
+#[test]
+fn fast_factor_vs_full(){
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    for seed in 0..10{
+        let mut rng = seeded(seed);
+        let x1 = noisy_courve_with(&mut rng,311);
+        let x2 = noisy_courve_with(&mut rng,257);
+        let full = dtw(&x1,&x2,Mode::Full,&dist).unwrap();
+        for &factor in &[2,3,4]{
+            let options = FastDtwOptions::new(10).factor(factor);
+            let fast = fast_dtw_with_options(&x1,&x2,&dist,false,&options).unwrap();
+            assert!(full.score <= fast.score + 0.001,"seed:{}",seed);
+            assert!(fast.score <= full.score * 1.2,"seed:{},{},{},{}",seed,factor,fast.score,full.score);
+            let (p1,p2) = fast.path.unwrap();
+            assert_eq!((p1[0],p2[0]),(0,0));
+            assert_eq!((p1[p1.len()-1],p2[p2.len()-1]),(310,256));
+        }
+        // No recursion at all.
+        let options = FastDtwOptions::new(1).min_length(312);
+        assert_eq!(fast_dtw_with_options(&x1,&x2,&dist,false,&options).unwrap(),full);
+        let default = fast_dtw(&x1,&x2,&dist,10,false).unwrap();
+        let options = FastDtwOptions::new(10).factor(2).min_length(12);
+        assert_eq!(fast_dtw_with_options(&x1,&x2,&dist,false,&options).unwrap(),default);
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+struct Event{mean:f32,sd:f32,length:usize}
+
+impl Coarsen for Event{
+    fn coarsen(chunk:&[Self])->Self{
+        let length:usize = chunk.iter().map(|e|e.length).sum();
+        let mean = chunk.iter().map(|e|e.mean * e.length as f32).sum::<f32>() / length as f32;
+        let sd = chunk.iter().map(|e|e.sd * e.length as f32).sum::<f32>() / length as f32;
+        Event{mean,sd,length}
+    }
+}
+
+#[test]
+fn fast_on_events(){
+    let to_events = |xs:Vec<f32>| xs.into_iter().enumerate()
+        .map(|(i,mean)|Event{mean,sd:0.1,length:1 + i % 4}).collect::<Vec<_>>();
+    let query = to_events(sin_curve(150));
+    let mut reference = to_events(vec![10.;300]);
+    reference.extend(query.iter());
+    reference.extend(to_events(vec![10.;51]));
+    let dist = |x:&Event,y:&Event| (x.mean-y.mean).powi(2) + (x.sd-y.sd).powi(2);
+    let aln = dtw(&query,&reference,Mode::FastSub(10),&dist).unwrap();
+    assert_eq!(aln.score,0.);
+    assert_eq!(aln.reference_span,Some((300,450)));
+    // The same merge given as a closure.
+    let options = FastDtwOptions::new(10);
+    let merged = fast_dtw_with_merge(&query,&reference,&dist,true,&options,&Event::coarsen).unwrap();
+    assert_eq!(aln,merged);
+}
+
+#[test]
+fn fast_merge_vs_coarsen(){
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let x1 = noisy_courve(300);
+    let x2 = noisy_courve(341);
+    for &factor in &[2,3]{
+        let options = FastDtwOptions::new(5).factor(factor);
+        let by_trait = fast_dtw_with_options(&x1,&x2,&dist,false,&options).unwrap();
+        let mean = |xs:&[f32]| xs.iter().sum::<f32>() / xs.len() as f32;
+        let by_closure = fast_dtw_with_merge(&x1,&x2,&dist,false,&options,&mean).unwrap();
+        assert_eq!(by_trait.path,by_closure.path);
+    }
+}
+
+#[test]
+fn transformed_all_modes(){
+    let dist = |x:&[f32],y:&[f32]| squared_euclidean(x,y);
+    let x1 = noisy_courve(120);
+    let mut x2 = noise(60);
+    x2.extend(noisy_courve(140));
+    let modes = [Mode::Full,Mode::Sub,Mode::Fast(5),Mode::FastSub(5),Mode::SakoeChiba(21),Mode::SubSakoeChiba(21),
+                 Mode::Itakura(41),Mode::LinearFull,Mode::LinearSub,Mode::QuickSub];
+    for &transform in &[Transform::Derivative,Transform::Slope(5),Transform::Paa(8,4),Transform::Raw(5)]{
+        let f1 = transform.apply(&x1);
+        let f2 = transform.apply(&x2);
+        assert_eq!((f1.len(),f2.len()),(x1.len(),x2.len()));
+        for &mode in &modes{
+            let aln = dtw_transformed(&x1,&x2,mode,transform,&dist).unwrap();
+            assert_eq!(aln,dtw_dependent(&f1,&f2,mode,&dist).unwrap(),"{},{}",transform,mode);
+            if let Some((p1,p2)) = aln.path{
+                assert_eq!(p1[p1.len()-1],x1.len()-1);
+                assert!(p2.iter().all(|&j|j < x2.len()));
+            }
+        }
+    }
+}
+
+#[test]
+fn derivative_ignores_drift(){
+    // A warped copy, which repeats every fifth sample, on a baseline drifting upwards.
+    let x1 = sin_curve(100);
+    let warp:Vec<usize> = (0..100).flat_map(|i| if i % 5 == 0 { vec![i,i] } else { vec![i] }).collect();
+    let x2:Vec<f32> = warp.iter().enumerate().map(|(j,&i)| x1[i] + 1. + 0.005 * j as f32).collect();
+    let dist = |x:&[f32],y:&[f32]| squared_euclidean(x,y);
+    let raw = dtw(&x1,&x2,Mode::Full,&|x:&f32,y:&f32|(x-y).powi(2)).unwrap();
+    let derivative = dtw_transformed(&x1,&x2,Mode::Full,Transform::Derivative,&dist).unwrap();
+    // The distance of the path from the true warping.
+    let deviation = |aln:&Alignment<f32>|{
+        let (p1,p2) = aln.path.clone().unwrap();
+        p1.iter().zip(p2.iter()).map(|(&i,&j)|(i as f32 - warp[j] as f32).abs()).sum::<f32>() / p1.len() as f32
+    };
+    assert!(deviation(&derivative) * 2. < deviation(&raw),"{},{}",deviation(&derivative),deviation(&raw));
+}
+
+#[test]
+fn lower_bounds_below_chiba(){
+    use rand::Rng;
+    use lower_bounds::*;
+    let abs = |x:&f32,y:&f32| (x-y).abs();
+    let squared = |x:&f32,y:&f32| (x-y).powi(2);
+    type Dist<'a> = &'a dyn Fn(&f32,&f32)->f32;
+    let dists:[Dist;2] = [&abs,&squared];
+    for seed in 0..500{
+        let mut rng = seeded(seed);
+        let len = rng.gen_range(1,60);
+        let radius = rng.gen_range(1,12);
+        let (x,y) = if rng.gen_range(0,2) == 0 {
+            (noise_with(&mut rng,len),noise_with(&mut rng,len))
+        } else {
+            (noisy_courve_with(&mut rng,len),sin_curve(len))
+        };
+        for dist in dists.iter(){
+            let score = dtw(&x,&y,Mode::SakoeChiba(2*radius+1),dist).unwrap().score;
+            let tol = score * 0.0001 + 0.0001;
+            let (lower,upper) = envelope(&y,radius);
+            let keogh = lb_keogh(&x,&lower,&upper,dist);
+            let bounds = [("Kim",lb_kim(&x,&y,dist)),("Keogh",keogh),
+                          ("Improved",lb_improved(&x,&y,radius,dist)),
+                          ("Enhanced",lb_enhanced(&x,&y,radius,4,dist)),
+                          ("Webb",lb_webb(&x,&y,radius,dist))];
+            for &(name,lb) in bounds.iter(){
+                assert!(lb <= score + tol,"seed:{},{},{},{}",seed,name,lb,score);
+            }
+            assert!(keogh <= bounds[2].1 && keogh <= bounds[4].1,"seed:{}",seed);
+        }
+    }
+}
+
+#[test]
+fn envelope_vs_naive(){
+    use rand::Rng;
+    for seed in 0..300{
+        let mut rng = seeded(seed);
+        let len = rng.gen_range(0,200);
+        let radius = rng.gen_range(0,30);
+        // Ties happen often in rounded values.
+        let xs:Vec<f32> = noise_with(&mut rng,len).into_iter().map(|x|(x*3.).round()).collect();
+        let (lower,upper) = lower_bounds::envelope(&xs,radius);
+        assert_eq!((lower.len(),upper.len()),(len,len));
+        for i in 0..len{
+            let window = &xs[i.saturating_sub(radius)..(i+radius+1).min(len)];
+            assert_eq!(lower[i],window.iter().fold(f32::INFINITY,|m,&x|m.min(x)),"seed:{}",seed);
+            assert_eq!(upper[i],window.iter().fold(f32::NEG_INFINITY,|m,&x|m.max(x)),"seed:{}",seed);
+        }
+    }
+    let xs = vec![1.,2.,3.];
+    assert_eq!(lower_bounds::envelope(&xs,usize::MAX),(vec![1.;3],vec![3.;3]));
+}
+
+#[test]
+fn cutoff_all_modes(){
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let modes = [Mode::Full,Mode::Sub,Mode::SakoeChiba(21),Mode::SubSakoeChiba(21),Mode::Itakura(41),
+                 Mode::Fast(5),Mode::FastSub(5),Mode::QuickSub,Mode::LinearFull,Mode::LinearSub];
+    for _ in 0..5{
+        let x1 = noisy_courve(80);
+        let mut x2 = noise(50);
+        x2.extend(noisy_courve(90));
+        for &mode in &modes{
+            let aln = dtw(&x1,&x2,mode,&dist).unwrap();
+            assert_eq!(dtw_with_cutoff(&x1,&x2,mode,&dist,f32::INFINITY).unwrap(),aln,"{}",mode);
+            assert_eq!(dtw_with_cutoff(&x1,&x2,mode,&dist,aln.score).unwrap(),aln,"{}",mode);
+            match dtw_with_cutoff(&x1,&x2,mode,&dist,aln.score * 0.99).unwrap_err(){
+                DtwError::Abandoned{row,total} => assert!(1 <= row && row <= total && total == x1.len(),"{}",mode),
+                err => panic!("{},{}",mode,err),
+            }
+        }
+    }
+}
+
+#[test]
+fn cutoff_stops_early(){
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    let x1 = sin_curve(100);
+    let x2:Vec<f32> = sin_curve(120).into_iter().map(|x| x + 10.).collect();
+    for &mode in &[Mode::Full,Mode::Sub,Mode::SakoeChiba(21),Mode::SubSakoeChiba(21),Mode::Itakura(41),
+                   Mode::Fast(5),Mode::FastSub(5),Mode::QuickSub]{
+        let err = dtw_with_cutoff(&x1,&x2,mode,&dist,300.).unwrap_err();
+        // Every cell costs at least 64, and the fourth row exceeds the cutoff.
+        assert_eq!(err,DtwError::Abandoned{row:4,total:100},"{}",mode);
+    }
+    let err = dtw_with_cutoff(&x1,&x2,Mode::LinearFull,&dist,300.).unwrap_err();
+    assert_eq!(err,DtwError::Abandoned{row:100,total:100});
+}
+
+#[test]
+fn pruned_vs_plain(){
+    use rand::Rng;
+    let dist = |x:&f32,y:&f32| (x-y).powi(2);
+    for seed in 0..200{
+        let mut rng = seeded(seed);
+        let (len1,len2) = (rng.gen_range(1,80),rng.gen_range(1,80));
+        let x1 = noisy_courve_with(&mut rng,len1);
+        let x2 = noisy_courve_with(&mut rng,len2);
+        assert_eq!(pruned_dtw(&x1,&x2,&dist,None),dtw(&x1,&x2,Mode::Full,&dist),"seed:{}",seed);
+        let x2 = if rng.gen_range(0,2) == 0 { noise_with(&mut rng,x1.len()) } else { x2 };
+        for &band in &[3,11,31]{
+            let aln = dtw(&x1,&x2,Mode::SakoeChiba(band),&dist);
+            assert_eq!(pruned_dtw(&x1,&x2,&dist,Some(band)),aln,"seed:{}",seed);
+            let score = aln.unwrap().score;
+            for &cutoff in &[score,score * 0.9,score * 0.1]{
+                let mode = Mode::SakoeChiba(band);
+                assert_eq!(ea_pruned_dtw(&x1,&x2,&dist,Some(band),cutoff),dtw_with_cutoff(&x1,&x2,mode,&dist,cutoff),"seed:{}",seed);
+                let mode = Mode::Full;
+                assert_eq!(ea_pruned_dtw(&x1,&x2,&dist,None,cutoff),dtw_with_cutoff(&x1,&x2,mode,&dist,cutoff),"seed:{}",seed);
+            }
+        }
+    }
+}
+
+#[test]
+fn pruned_skips_cells(){
+    let count = std::cell::Cell::new(0);
+    let dist = |x:&f32,y:&f32| { count.set(count.get() + 1); (x-y).powi(2) };
+    let x1 = sin_curve(200);
+    let x2:Vec<f32> = x1.iter().map(|x| x + 0.01).collect();
+    let aln = pruned_dtw(&x1,&x2,&dist,None).unwrap();
+    assert!(count.get() < 200 * 200 / 2,"{}",count.get());
+    count.set(0);
+    assert_eq!(dtw(&x1,&x2,Mode::Full,&dist).unwrap(),aln);
+    assert_eq!(count.get(),200 * 200);
+    count.set(0);
+    let x3:Vec<f32> = x1.iter().map(|x| x + 10.).collect();
+    let err = ea_pruned_dtw(&x1,&x3,&dist,None,aln.score).unwrap_err();
+    assert_eq!(err,DtwError::Abandoned{row:1,total:200});
+    // The diagonal and the first row until it exceeds the cutoff.
+    assert!(count.get() <= 200 + 1,"{}",count.get());
+}
+
+#[test]
+fn scouting_reports_location(){
+    let query = noisy_courve(300);
+    let reference:Vec<_> = noise(500).into_iter()
+        .chain(query.iter().map(|x| x + 0.01))
+        .chain(noise(1000))
+        .collect();
+    let scout = scouting_dtw(&query,&reference,&hill,None,None).unwrap();
+    let (start,end) = scout.reference_span.unwrap();
+    assert!(start.max(500) - start.min(500) < 20 && end.max(800) - end.min(800) < 20,"{},{}",start,end);
+    let aln = scouting_threshold_dtw_with_path(&query,&reference,&hill,None,None,f32::INFINITY).unwrap();
+    assert_eq!(aln.reference_span,Some((start,end)));
+    assert_eq!(aln.query_span,(0,300));
+    assert!((aln.score - scout.score).abs() < 0.01,"{},{}",aln.score,scout.score);
+    let costs = aln.local_costs(&query,&reference,&hill).unwrap();
+    let sum:f32 = costs.iter().sum();
+    assert!((sum - aln.score).abs() < 0.01,"{},{}",sum,aln.score);
+}
@@ -1,54 +1,197 @@
+use super::quickdtw::min_with_start;
+use super::Alignment;
+use super::DtwError;
+use super::Normalization;
 use num::Float;
 
 /// sub dynamic time warping with early abandoning.
 /// This pruning idea comes from a simple observation that
 /// once the score of dtw exceed a threshold, it
 /// won't reach smaller score.
-/// It returns Err(_) when it reaches threshold or
-/// error.
+/// It returns Err(DtwError::Abandoned{..}) when it reaches threshold,
+/// or other DtwError for invalid input.
+/// The path is not computed, but the location of the optimal alignment
+/// in the reference is reported.
 #[inline]
 pub fn thresholddtw<D, F, T>(
     x1: &[D],
     x2: &[D],
     dist: &F,
     threshold: T,
-) -> Result<(T, (Vec<usize>, Vec<usize>), usize), String>
+) -> Result<Alignment<T>, DtwError>
 where
     F: Fn(&D, &D) -> T,
     T: Float,
+{
+    let (scores, starts, _) = fill_rows(x1, x2, dist, |_, score, _| score > threshold)?;
+    let (score, end) =
+        scores
+            .iter()
+            .enumerate()
+            .skip(1)
+            .fold(
+                (T::infinity(), 0),
+                |acc, (j, &x)| {
+                    if acc.0 < x {
+                        acc
+                    } else {
+                        (x, j)
+                    }
+                },
+            );
+    let mut aln = Alignment::score_only(score, x1.len());
+    aln.reference_span = Some((starts[end], end));
+    Ok(aln)
+}
+
+/// sub dynamic time warping with early abandoning on the normalized score.
+/// The threshold is compared with the score divided by the query length or
+/// by the path length, so that one threshold can be used for queries of different lengths.
+/// The score of the returned alignment is normalized in the same way.
+/// With Normalization::PathLength, the alignment minimizing the normalized score
+/// among the optimal alignments ending at each reference position is reported,
+/// and the computation is abandoned only when the threshold can not be reached
+/// by any extension of the current row, thus less often than by query length.
+pub fn thresholddtw_normalized<D, F, T>(
+    x1: &[D],
+    x2: &[D],
+    dist: &F,
+    threshold: T,
+    by: Normalization,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+{
+    let x1len = x1.len();
+    let x2len = x2.len();
+    let length = |x: usize| T::from(x).unwrap();
+    let (scores, starts, lengths) = match by {
+        Normalization::QueryLength => {
+            let threshold = threshold * length(x1len);
+            fill_rows(x1, x2, dist, |_, score, _| score > threshold)?
+        }
+        Normalization::PathLength => fill_rows(x1, x2, dist, |(i, j), score, len| {
+            // The path can be at most this long after the extension.
+            let max_len = len + (x1len - i) + (x2len - j);
+            score > threshold * length(max_len)
+        })?,
+    };
+    let (score, end) = scores
+        .iter()
+        .zip(lengths.iter())
+        .enumerate()
+        .skip(1)
+        .map(|(j, (&score, &len))| match by {
+            Normalization::QueryLength => (score / length(x1len), j),
+            Normalization::PathLength => (score / length(len), j),
+        })
+        .fold(
+            (T::infinity(), 0),
+            |acc, x| if acc.0 < x.0 { acc } else { x },
+        );
+    let mut aln = Alignment::score_only(score, x1len);
+    aln.reference_span = Some((starts[end], end));
+    Ok(aln)
+}
+
+// The last row of the DP table as (scores, start positions, path lengths).
+type LastRow<T> = (Vec<T>, Vec<usize>, Vec<usize>);
+
+// Fill the DP table row by row and return the last row.
+// The computation is abandoned when is_over(position, score, path length) holds
+// for every cell of a row.
+fn fill_rows<D, F, T, C>(x1: &[D], x2: &[D], dist: &F, is_over: C) -> Result<LastRow<T>, DtwError>
+where
+    F: Fn(&D, &D) -> T,
+    T: Float,
+    C: Fn((usize, usize), T, usize) -> bool,
 {
     // x1 is query,x2 is reference
     let x1len = x1.len();
     let x2len = x2.len();
     if x1len == 0 || x2len == 0 {
-        return Err("the length of given time series may be empty".to_string());
+        return Err(DtwError::EmptyInput);
     }
     // dynamic programming path will be filled in
     // reference order.
+    // start[j] is the reference position where the alignment ending at j starts.
     let bignum = T::epsilon().recip();
     let mut previous = vec![T::zero(); x2len + 1];
     let mut current = vec![bignum; x2len + 1];
+    // In the top row, the match transition from j-1 is always preferred,
+    // thus the alignment ending at (1,j) starts at j-1.
+    let mut previous_start: Vec<usize> = (0..x2len + 1).collect();
+    let mut current_start = vec![0; x2len + 1];
+    let mut previous_len = vec![0; x2len + 1];
+    let mut current_len = vec![0; x2len + 1];
     let mut early_return;
     for i in 1..x1len + 1 {
         early_return = true;
         for j in 1..x2len + 1 {
-            let min: T = previous[j].min(previous[j - 1].min(current[j - 1]));
+            let (min, (start, len)) = min_with_start(
+                (
+                    previous[j - 1],
+                    (previous_start[j - 1], previous_len[j - 1]),
+                ),
+                (current[j - 1], (current_start[j - 1], current_len[j - 1])),
+                (previous[j], (previous_start[j], previous_len[j])),
+            );
             current[j] = min + dist(&x1[i - 1], &x2[j - 1]);
-            early_return = (current[j] > threshold) & early_return;
+            current_start[j] = start;
+            current_len[j] = len + 1;
+            early_return &= is_over((i, j), current[j], current_len[j]);
         }
         if early_return {
-            return Err(format!("{},{}", i, x1len));
+            return Err(DtwError::Abandoned {
+                row: i,
+                total: x1len,
+            });
         }
-        for k in 0..x2len + 1 {
-            previous[k] = current[k];
-            current[k] = bignum;
+        std::mem::swap(&mut previous, &mut current);
+        std::mem::swap(&mut previous_start, &mut current_start);
+        std::mem::swap(&mut previous_len, &mut current_len);
+        for x in current.iter_mut() {
+            *x = bignum;
         }
     }
-    Ok((
-        previous
-            .into_iter()
-            .fold(T::infinity(), |acc, x| if acc < x { acc } else { x }),
-        (vec![], vec![]),
-        0,
-    ))
+    Ok((previous, previous_start, previous_len))
+}
+
+#[test]
+fn location_test() {
+    let x1 = vec![1, 2, 3];
+    let x2 = vec![0, 0, 1, 2, 2, 3, 0];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = thresholddtw(&x1, &x2, &d, 10.).unwrap();
+    assert_eq!(aln.score, 0.);
+    assert_eq!(aln.reference_span, Some((2, 6)));
+    let aln = thresholddtw(&x1, &[1, 2, 3], &d, 10.).unwrap();
+    assert_eq!(aln.reference_span, Some((0, 3)));
+}
+
+#[test]
+fn normalized_test() {
+    let x1 = vec![1, 2, 3, 4];
+    let x2 = vec![0, 0, 1, 2, 2, 3, 5, 0];
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let aln = thresholddtw_normalized(&x1, &x2, &d, 1., Normalization::QueryLength).unwrap();
+    assert_eq!(aln.score, 0.25);
+    assert_eq!(aln.reference_span, Some((2, 7)));
+    // The path is (1,2,2,3,5) aligned to (1,2,2,3,4).
+    let aln = thresholddtw_normalized(&x1, &x2, &d, 1., Normalization::PathLength).unwrap();
+    assert_eq!(aln.score, 0.2);
+    let res = thresholddtw_normalized(&x1, &[9, 9, 9], &d, 1., Normalization::QueryLength);
+    assert_eq!(res.err(), Some(DtwError::Abandoned { row: 1, total: 4 }));
+}
+
+#[test]
+fn same_location_as_quickdtw() {
+    // Both (0,1),(1,2),(1,3),(2,4) and (0,3),(1,3),(2,4) cost 1, and (1,3) is reached
+    // by a tie between insertion and deletion, which should be resolved as quickdtw does.
+    let d = |x: &i32, y: &i32| (x - y).abs() as f32;
+    let (x1, x2) = (vec![3, 2, 0], vec![3, 3, 1, 2, 0]);
+    let quick = super::quickdtw::quickdtw(&x1, &x2, &d, f32::INFINITY).unwrap();
+    let aln = thresholddtw(&x1, &x2, &d, f32::INFINITY).unwrap();
+    assert_eq!(aln.reference_span, quick.reference_span);
 }
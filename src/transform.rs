@@ -0,0 +1,216 @@
+use super::dtw;
+use super::dtw_dependent;
+use super::Alignment;
+use super::DtwError;
+use super::Mode;
+use num::Float;
+
+/// Preprocessing which turns a series into a feature sequence before the alignment.
+/// Every transform makes exactly one feature for each sample,
+/// so the path and the reference span of the alignment of the features
+/// are the indices of the original series.
+/// Windows are centered at each sample and padded with the first/last sample at the ends.
+/// The width of a window and the number of segments should be positive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    /// The estimated derivative of Derivative DTW (Keogh and Pazzani, 2001).
+    /// The first and last samples take the estimates of their neighbours.
+    Derivative,
+    /// The slope of the least squares line over the window of the given width (shapeDTW).
+    Slope(usize),
+    /// Piecewise aggregate approximation of the window (shapeDTW):
+    /// (width, segments) gives the means of the segments of the window of the width.
+    Paa(usize, usize),
+    /// The window of the given width itself (shapeDTW).
+    Raw(usize),
+}
+
+impl std::fmt::Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Transform::Derivative => write!(f, "Derivative"),
+            Transform::Slope(w) => write!(f, "Slope({})", w),
+            Transform::Paa(w, s) => write!(f, "Paa({}_{})", w, s),
+            Transform::Raw(w) => write!(f, "Raw({})", w),
+        }
+    }
+}
+
+impl Transform {
+    /// The feature of each sample. Derivative and Slope give features of length 1.
+    pub fn apply<T: Float>(&self, xs: &[T]) -> Vec<Vec<T>> {
+        match *self {
+            Transform::Paa(w, s) => (0..xs.len()).map(|i| paa(&window(xs, i, w), s)).collect(),
+            Transform::Raw(w) => (0..xs.len()).map(|i| window(xs, i, w)).collect(),
+            Transform::Derivative | Transform::Slope(_) => self
+                .scalars(xs)
+                .unwrap()
+                .into_iter()
+                .map(|x| vec![x])
+                .collect(),
+        }
+    }
+    // The features as scalars, if each feature has only one value.
+    fn scalars<T: Float>(&self, xs: &[T]) -> Option<Vec<T>> {
+        match *self {
+            Transform::Derivative => Some(derivative(xs)),
+            Transform::Slope(w) => Some((0..xs.len()).map(|i| slope(&window(xs, i, w))).collect()),
+            Transform::Paa(..) | Transform::Raw(_) => None,
+        }
+    }
+    fn validate(&self) -> Result<(), DtwError> {
+        match *self {
+            Transform::Slope(0)
+            | Transform::Paa(0, _)
+            | Transform::Paa(_, 0)
+            | Transform::Raw(0) => Err(DtwError::InvalidTransform),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The estimated derivative used by Derivative DTW.
+/// For 0 < i < n-1, it is ((x[i]-x[i-1]) + (x[i+1]-x[i-1])/2)/2.
+pub fn derivative<T: Float>(xs: &[T]) -> Vec<T> {
+    let two = T::one() + T::one();
+    match xs.len() {
+        0 => vec![],
+        1 => vec![T::zero()],
+        2 => vec![xs[1] - xs[0]; 2],
+        len => {
+            let mut ds = Vec::with_capacity(len);
+            ds.push(T::zero());
+            for i in 1..len - 1 {
+                ds.push(((xs[i] - xs[i - 1]) + (xs[i + 1] - xs[i - 1]) / two) / two);
+            }
+            ds[0] = ds[1];
+            ds.push(ds[len - 2]);
+            ds
+        }
+    }
+}
+
+/// Dynamic time warping between the features of x1 and x2.
+/// dist compares two features, e.g. `squared_euclidean`. Every mode is available.
+/// It returns Err(DtwError::InvalidTransform) if a window or the number of segments is zero.
+/// X1 IS QUERY AND X2 IS REFERENCE.
+/// ```rust
+/// use dtw::{dtw_transformed, squared_euclidean, Mode, Transform};
+/// let x1: Vec<f64> = (0..50).map(|i| (i as f64 / 5.).sin()).collect();
+/// // The same curve on a drifted baseline.
+/// let x2: Vec<f64> = x1.iter().map(|x| x + 3.).collect();
+/// let dist = |x: &[f64], y: &[f64]| squared_euclidean(x, y);
+/// let aln = dtw_transformed(&x1, &x2, Mode::Full, Transform::Derivative, &dist).unwrap();
+/// assert!(aln.score < 0.000_001);
+/// ```
+pub fn dtw_transformed<T, F>(
+    x1: &[T],
+    x2: &[T],
+    mode: Mode,
+    transform: Transform,
+    dist: &F,
+) -> Result<Alignment<T>, DtwError>
+where
+    F: Fn(&[T], &[T]) -> T,
+    T: Float,
+{
+    transform.validate()?;
+    if x1.is_empty() || x2.is_empty() {
+        return Err(DtwError::EmptyInput);
+    }
+    // Features of one value are aligned as scalars, so that each of them is not allocated.
+    if let (Some(x1), Some(x2)) = (transform.scalars(x1), transform.scalars(x2)) {
+        let dist = |x: &T, y: &T| dist(std::slice::from_ref(x), std::slice::from_ref(y));
+        return dtw(&x1, &x2, mode, &dist);
+    }
+    let x1 = transform.apply(x1);
+    let x2 = transform.apply(x2);
+    dtw_dependent(&x1, &x2, mode, dist)
+}
+
+// The window of the width centered at i. Positions outside the series take the nearest sample.
+fn window<T: Float>(xs: &[T], i: usize, width: usize) -> Vec<T> {
+    let last = xs.len() as isize - 1;
+    let start = i as isize - (width / 2) as isize;
+    (start..start + width as isize)
+        .map(|j| xs[j.max(0).min(last) as usize])
+        .collect()
+}
+
+fn slope<T: Float>(ws: &[T]) -> T {
+    let n = T::from(ws.len()).unwrap();
+    let two = T::one() + T::one();
+    let mean_t = (n - T::one()) / two;
+    let mean_x = ws.iter().fold(T::zero(), |acc, &x| acc + x) / n;
+    let (cov, var) = ws
+        .iter()
+        .enumerate()
+        .fold((T::zero(), T::zero()), |(cov, var), (t, &x)| {
+            let dt = T::from(t).unwrap() - mean_t;
+            (cov + dt * (x - mean_x), var + dt * dt)
+        });
+    if var > T::zero() {
+        cov / var
+    } else {
+        T::zero()
+    }
+}
+
+// The means of segments pieces of the window. There are at most ws.len() pieces.
+fn paa<T: Float>(ws: &[T], segments: usize) -> Vec<T> {
+    let segments = segments.min(ws.len());
+    (0..segments)
+        .map(|k| {
+            let (start, end) = (k * ws.len() / segments, (k + 1) * ws.len() / segments);
+            let sum = ws[start..end].iter().fold(T::zero(), |acc, &x| acc + x);
+            sum / T::from(end - start).unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn derivative_test() {
+    let xs = vec![0., 1., 3., 6.];
+    assert_eq!(derivative(&xs), vec![1.25, 1.25, 2.25, 2.25]);
+    assert_eq!(derivative(&[2., 5.]), vec![3., 3.]);
+    assert_eq!(derivative(&[2.]), vec![0.]);
+}
+
+#[test]
+fn window_test() {
+    let xs = vec![1., 2., 3., 4.];
+    assert_eq!(window(&xs, 0, 3), vec![1., 1., 2.]);
+    assert_eq!(window(&xs, 3, 4), vec![2., 3., 4., 4.]);
+    assert_eq!(window(&xs, 1, 1), vec![2.]);
+}
+
+#[test]
+fn descriptor_test() {
+    assert_eq!(slope(&[1., 3., 5., 7.]), 2.);
+    assert_eq!(slope(&[4.]), 0.);
+    assert_eq!(paa(&[1., 3., 5., 7., 9.], 2), vec![2., 7.]);
+    assert_eq!(paa(&[1., 3.], 5), vec![1., 3.]);
+    let xs = vec![0., 1., 2., 3., 4.];
+    let features = Transform::Slope(3).apply(&xs);
+    assert_eq!(features[0], vec![0.5]);
+    assert_eq!(features[2], vec![1.]);
+    assert_eq!(Transform::Raw(3).apply(&xs).len(), xs.len());
+}
+
+#[test]
+fn invalid_transform_test() {
+    let xs = vec![0., 1., 2.];
+    let dist = |x: &[f64], y: &[f64]| (x[0] - y[0]).powi(2);
+    for &transform in &[
+        Transform::Slope(0),
+        Transform::Paa(0, 2),
+        Transform::Paa(3, 0),
+        Transform::Raw(0),
+    ] {
+        assert_eq!(
+            dtw_transformed(&xs, &xs, Mode::Full, transform, &dist),
+            Err(DtwError::InvalidTransform)
+        );
+    }
+    assert!(dtw_transformed(&xs, &xs, Mode::Full, Transform::Raw(1), &dist).is_ok());
+}
@@ -1,16 +1,169 @@
+use super::dtw_chiba::dtw_chiba;
+use super::lower_bounds;
+use super::Alignment;
+use super::IndexError;
+use memmap2::Mmap;
 use num::Float;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+// The running sums for z-normalization are recomputed once in this number of windows,
+// so that floating point errors do not accumulate.
+const EPOCH: usize = 100_000;
+
+// Magic number and version of the index file. See `DynamicTimeWarping::save`.
+const MAGIC: &[u8; 8] = b"DTWINDEX";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 32;
+
 /// Wrapper struct invented by Keogh
+/// The reference and its envelopes can be saved to a file by `save`,
+/// and memory-mapped back by `load`.
 #[derive(Debug)]
 pub struct DynamicTimeWarping<D,T,F>
 where F:Fn(&D,&D)->T, T:Float,D:Copy+PartialOrd{
-    reference:Vec<D>,
+    reference:Series<D>,
     dist:F,
-    lower_envelope:Vec<D>,
-    upper_envelope:Vec<D>,
+    lower_envelope:Series<D>,
+    upper_envelope:Series<D>,
     bandwidth:usize,
-    lowerbound_check_order:Vec<usize>,
 }
 
+// A series owned by the struct, or mapped from an index file.
+#[derive(Debug)]
+enum Series<D>{
+    Owned(Vec<D>),
+    // len elements from offset bytes of the map. offset is aligned to 8 bytes.
+    Mapped{map:Arc<Mmap>,offset:usize,len:usize,element:std::marker::PhantomData<D>},
+}
+
+impl<D> Deref for Series<D>{
+    type Target = [D];
+    fn deref(&self)->&[D]{
+        match self{
+            Series::Owned(xs) => xs,
+            // Safety: Mapped is made only by load for IndexElement types,
+            // after the size and the alignment of the region are checked.
+            Series::Mapped{map,offset,len,..} => unsafe{
+                std::slice::from_raw_parts(map.as_ptr().add(*offset) as *const D,*len)
+            },
+        }
+    }
+}
+
+mod sealed{
+    pub trait Sealed{}
+    impl Sealed for f32{}
+    impl Sealed for f64{}
+}
+
+/// Element types which can be stored in an index file, namely f32 and f64.
+pub trait IndexElement:Copy+PartialOrd+sealed::Sealed{
+    /// Size of an element in bytes, also used to tag the element type in the file.
+    const SIZE:usize;
+    /// Little endian bytes of the value.
+    fn write_le<W:Write>(&self,w:&mut W)->std::io::Result<()>;
+}
+
+impl IndexElement for f32{
+    const SIZE:usize = 4;
+    fn write_le<W:Write>(&self,w:&mut W)->std::io::Result<()>{
+        w.write_all(&self.to_le_bytes())
+    }
+}
+
+impl IndexElement for f64{
+    const SIZE:usize = 8;
+    fn write_le<W:Write>(&self,w:&mut W)->std::io::Result<()>{
+        w.write_all(&self.to_le_bytes())
+    }
+}
+
+// The number of bytes of len elements of size bytes, padded to 8 bytes.
+// None if it overflows.
+fn section_size(len:usize,size:usize)->Option<usize>{
+    len.checked_mul(size)?.checked_add(7).map(|bytes| bytes / 8 * 8)
+}
+
+/// Pruning statistics of a search over the windows of the reference.
+/// Each window is counted by the first stage which discarded it,
+/// thus the counts except `windows` sum up to `windows`.
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq)]
+pub struct PruningStats{
+    /// Number of windows examined.
+    pub windows:usize,
+    /// Windows pruned by LB_KimFL.
+    pub kim:usize,
+    /// Windows pruned by LB_Keogh with the envelope of the query.
+    pub keogh_query:usize,
+    /// Windows pruned by LB_Keogh with the envelope of the reference.
+    pub keogh_reference:usize,
+    /// Windows whose distance computation was abandoned early.
+    pub abandoned:usize,
+    /// Windows whose distance was computed to the end.
+    pub completed:usize,
+}
+
+// Transformation applied to each window of the reference before the comparison.
+// The transformation should be monotonically increasing, so that
+// the transformed envelope is the envelope of the transformed window.
+trait Window<D>{
+    // Move to the window reference[pos..pos+len]. Called with pos = 0,1,2,... in order.
+    fn slide(&mut self,reference:&[D],pos:usize,len:usize);
+    fn apply(&self,x:D)->D;
+}
+
+// The raw values.
+struct Raw;
+impl<D> Window<D> for Raw{
+    fn slide(&mut self,_:&[D],_:usize,_:usize){}
+    #[inline]
+    fn apply(&self,x:D)->D{x}
+}
+
+// Z-normalization with the running mean and standard deviation.
+struct ZNormalize<D>{
+    // Number of windows since the running sums were recomputed.
+    count:usize,
+    sum:D,
+    sum_sq:D,
+    mean:D,
+    std:D,
+}
+
+impl<D:Float> ZNormalize<D>{
+    fn new()->Self{
+        ZNormalize{count:0,sum:D::zero(),sum_sq:D::zero(),mean:D::zero(),std:D::one()}
+    }
+    fn set_moments(&mut self,len:usize){
+        let len = D::from(len).unwrap();
+        self.mean = self.sum / len;
+        let var = self.sum_sq / len - self.mean * self.mean;
+        // A flat window is only shifted.
+        self.std = if var > D::epsilon() { var.sqrt() } else { D::one() };
+    }
+}
+
+impl<D:Float> Window<D> for ZNormalize<D>{
+    fn slide(&mut self,reference:&[D],pos:usize,len:usize){
+        if pos == 0 || self.count == EPOCH{
+            self.count = 0;
+            let window = &reference[pos..pos+len];
+            self.sum = window.iter().fold(D::zero(),|acc,&x| acc + x);
+            self.sum_sq = window.iter().fold(D::zero(),|acc,&x| acc + x * x);
+        }else{
+            let (out,new) = (reference[pos-1],reference[pos+len-1]);
+            self.sum = self.sum + new - out;
+            self.sum_sq = self.sum_sq + new * new - out * out;
+        }
+        self.count += 1;
+        self.set_moments(len);
+    }
+    #[inline]
+    fn apply(&self,x:D)->D{(x - self.mean) / self.std}
+}
 
 impl<D,T,F> DynamicTimeWarping<D,T,F>
     where F:Fn(&D,&D)->T, T:Float,D:Copy+PartialOrd{
@@ -18,185 +171,472 @@ impl<D,T,F> DynamicTimeWarping<D,T,F>
     pub fn new(reference:Vec<D>,dist:F,bandwidth:usize)->DynamicTimeWarping<D,T,F>
     {
         let (lower_envelope,upper_envelope) = Self::envelope(&reference,bandwidth);
-        let lowerbound_check_order = Self::ordering(&reference);
-        DynamicTimeWarping{reference,
+        DynamicTimeWarping{reference:Series::Owned(reference),
                            dist,
-                           lower_envelope,
-                           upper_envelope,
-                           bandwidth,
-                           lowerbound_check_order}
-    }
-    // compute envelope for given sequence
-    fn envelope(events:&Vec<D>,bandwidth:usize)->(Vec<D>,Vec<D>){
-        let len = events.len();
-        let mut lower_envelope = vec![events[0];len];
-        let mut upper_envelope = vec![events[0];len];
-        for i in 0..events.len(){
-            let start = if i < bandwidth { 0 } else{ i - bandwidth };
-            let end = if i + bandwidth > len { len }else{i+bandwidth};
-            for j in start .. end{
-                if events[i] < lower_envelope[j] {
-                    lower_envelope[j] = events[i];
-                }
-                if upper_envelope[j] < events[i]{
-                    upper_envelope[j] = events[i];
-                }
-            }
-        }
-        (lower_envelope,upper_envelope)
+                           lower_envelope:Series::Owned(lower_envelope),
+                           upper_envelope:Series::Owned(upper_envelope),
+                           bandwidth}
+    }
+    /// The band width given at the construction.
+    pub fn bandwidth(&self)->usize{
+        self.bandwidth
+    }
+    // compute envelope for given sequence.
+    // The envelope at j covers events[j-bandwidth..=j+bandwidth].
+    fn envelope(events:&[D],bandwidth:usize)->(Vec<D>,Vec<D>){
+        lower_bounds::envelope(events,bandwidth)
     }
     // compute the order by which lower_bound_keogh() is executed.
-    fn ordering(events:&Vec<D>)->Vec<usize>{
+    fn ordering(events:&[D])->Vec<usize>{
         let mut events:Vec<_> = events.iter().enumerate().collect();
         events.sort_by(|a,b|(b.1).partial_cmp(a.1).unwrap());
         events.iter().map(|e|e.0).collect()
     }
     /// Dynamic time warping for subsequence banded dynamic time warping.
+    /// It returns the optimal score and the start position of the window in the reference.
     pub fn dtw(&self,query:&Vec<D>)->(T,usize){
-        let mut best_so_far :T= T::infinity();
-        let mut best_position = 0;
+        let (score,position,_) = self.dtw_with_stats(query);
+        (score,position)
+    }
+    /// Same as `dtw`, with the pruning statistics.
+    pub fn dtw_with_stats(&self,query:&[D])->(T,usize,PruningStats){
+        let (mut best_so_far,mut best_position) = (T::infinity(),0);
+        let stats = self.scan(query,&Self::ordering(query),Raw,|pos,score|{
+            if score < best_so_far{
+                best_so_far = score;
+                best_position = pos;
+            }
+            best_so_far
+        });
+        (best_so_far,best_position,stats)
+    }
+    /// The k best windows of the reference, in increasing order of score,
+    /// with their banded warping paths in the reference coordinate.
+    /// The windows are taken greedily from the best one, skipping a window if its start
+    /// is less than exclusion positions away from the start of an already taken window.
+    /// Thus, exclusion = query.len() means that the windows do not overlap.
+    /// The result is the same as taking the windows from the scores of all the windows,
+    /// while most of the windows are still pruned.
+    pub fn top_k(&self,query:&[D],k:usize,exclusion:usize)->(Vec<Alignment<T>>,PruningStats){
+        let (starts,stats) = self.scan_top_k(query,&Self::ordering(query),Raw,k,exclusion);
         let len = query.len();
-        let (mut lb_q,mut lb_r,mut lb_m) = (0,0,0);
+        let hits = starts.into_iter()
+            .map(|start| self.path(query,&self.reference[start..start+len],start))
+            .collect();
+        (hits,stats)
+    }
+    // Start positions of the k best windows.
+    fn scan_top_k<W:Window<D>>(&self,query:&[D],order:&[usize],window:W,
+                               k:usize,exclusion:usize)->(Vec<usize>,PruningStats){
+        if k == 0{
+            return (vec![],PruningStats::default());
+        }
+        // (score,start) of the windows which can be taken.
+        let mut survivors:Vec<(T,usize)> = vec![];
+        let stats = self.scan(query,order,window,|pos,score|{
+            survivors.push((score,pos));
+            // If 2k-1 mutually separated windows are better than a window,
+            // it is never taken, because each taken window excludes at most two of them.
+            let threshold = separated(&mut survivors,2*k-1,exclusion)
+                .get(2*k-2).map(|&(score,_)|score).unwrap_or_else(T::infinity);
+            survivors.retain(|&(score,_)| score <= threshold);
+            threshold
+        });
+        let hits = separated(&mut survivors,k,exclusion).into_iter().map(|(_,start)|start).collect();
+        (hits,stats)
+    }
+    // The banded warping path between the query and the window starting at start.
+    fn path(&self,query:&[D],subref:&[D],start:usize)->Alignment<T>{
+        if self.bandwidth == 0{
+            let score = query.iter().zip(subref.iter())
+                .fold(T::zero(),|acc,(x,y)| acc + (self.dist)(x,y));
+            let x1path:Vec<_> = (0..query.len()).collect();
+            let x2path = (start..start+query.len()).collect();
+            return Alignment::from_path(score,(x1path,x2path));
+        }
+        let aln = dtw_chiba(query,subref,&self.dist,2*self.bandwidth+1,T::infinity()).unwrap();
+        let (x1path,x2path) = aln.path.unwrap();
+        let x2path = x2path.into_iter().map(|j| j + start).collect();
+        Alignment::from_path(aln.score,(x1path,x2path))
+    }
+    // Search the windows by the cascade of LB_KimFL, LB_Keogh(EQ), LB_Keogh(EC),
+    // and the banded dynamic time warping with the cumulative lower bound.
+    // The query should be transformed in advance. order is the order to compute LB_Keogh.
+    // accept is called with the start position and the score of each window computed to the end,
+    // and returns the threshold: windows with larger scores are pruned afterwards.
+    fn scan<W,A>(&self,query:&[D],order:&[usize],mut window:W,mut accept:A)->PruningStats
+    where W:Window<D>, A:FnMut(usize,T)->T{
+        let mut stats = PruningStats::default();
+        let len = query.len();
+        if len == 0 || self.reference.len() < len{
+            return stats;
+        }
         let (query_lower_env,query_upper_env) = Self::envelope(query,self.bandwidth);
-        let query_compare_ordering = Self::ordering(query);
-        for (pos,subref) in self.reference.windows(query.len()).enumerate(){
-            // no LB_Kim because its power is weak for very long query.
-//            eprintln!("start position:{}",pos);
-            let lb_query = self.lowerbound_keogh(subref,
-                                                 &query_lower_env,
-                                                 &query_upper_env,
-                                                 &query_compare_ordering,
-                                                 best_so_far);
-//            eprintln!("lower bound:{:?}(query)",lb_query.to_f32());
+        let mut best_so_far :T= T::infinity();
+        let mut candidate = query.to_vec();
+        let mut lb_query_each = vec![T::zero();len];
+        let mut lb_ref_each = vec![T::zero();len];
+        let mut cumulative_lb = vec![T::zero();len+1];
+        for pos in 0..self.reference.len() - len + 1{
+            stats.windows += 1;
+            window.slide(&self.reference,pos,len);
+            let subref = &self.reference[pos..pos+len];
+            let lb_kim = self.lowerbound_kim(query,|i| window.apply(subref[i]),best_so_far);
+            if lb_kim > best_so_far{
+                stats.kim += 1;
+                continue;
+            }
+            let lb_query = self.lowerbound_keogh(|i| window.apply(subref[i]),
+                                                 |i| query_lower_env[i],
+                                                 |i| query_upper_env[i],
+                                                 order,
+                                                 best_so_far,
+                                                 &mut lb_query_each);
             if lb_query > best_so_far{
-                lb_q +=1;
+                stats.keogh_query += 1;
                 continue;
             }
-            let lb_ref = self.lowerbound_keogh(query,
-                                               &self.lower_envelope[pos..pos+len],
-                                               &self.upper_envelope[pos..pos+len],
-                                                &query_compare_ordering,
-                                               best_so_far);
-//            eprintln!("lower bound:{:?}(ref)",lb_ref.to_f32());
+            let lower = &self.lower_envelope[pos..pos+len];
+            let upper = &self.upper_envelope[pos..pos+len];
+            let lb_ref = self.lowerbound_keogh(|i| query[i],
+                                               |i| window.apply(lower[i]),
+                                               |i| window.apply(upper[i]),
+                                               order,
+                                               best_so_far,
+                                               &mut lb_ref_each);
             if lb_ref > best_so_far{
-                lb_r+=1;
+                stats.keogh_reference += 1;
                 continue;
             }
-            let cumulative_lb = if lb_ref < lb_query{
-                self.cumulative_lower_bound(subref,
-                                             &query_lower_env,
-                                             &query_upper_env,
-                                             lb_query)
-            }else{
-                self.cumulative_lower_bound(query,
-                                             &self.lower_envelope[pos..pos+len],
-                                             &self.upper_envelope[pos..pos+len],
-                                             lb_ref)
-            };
-            let score = self.chiba_dtw_with_lower_bound(query,
-                                                    subref,
-                                                    &cumulative_lb,
-                                                    best_so_far,
-                                                    &mut lb_m);
-            if score < best_so_far{
-                best_so_far = score;
-                best_position = pos;
-            };
-//            eprintln!("best so far {:?}",best_so_far.to_f32());
+            // cumulative_lb[i] is the sum of the tighter lower bound from i to the end.
+            let each = if lb_ref < lb_query { &lb_query_each } else { &lb_ref_each };
+            for i in (0..len).rev(){
+                cumulative_lb[i] = cumulative_lb[i+1] + each[i];
+            }
+            for (c,&x) in candidate.iter_mut().zip(subref.iter()){
+                *c = window.apply(x);
+            }
+            match self.chiba_dtw_with_lower_bound(query,&candidate,&cumulative_lb,best_so_far){
+                Some(score) => {
+                    stats.completed += 1;
+                    best_so_far = accept(pos,score);
+                }
+                None => stats.abandoned += 1,
+            }
         }
-        eprintln!("query:{},reference:{},while main dtw:{}",lb_q,lb_r,lb_m);
-        (best_so_far,best_position)
+        stats
     }
-    fn lowerbound_keogh(&self,events:&[D],lower:&[D],upper:&[D],order:&[usize],
-                        best_so_far:T)->T{
+    // LB_KimFL: the first and the last cells, followed by the second and
+    // the second last ones, with early abandoning.
+    fn lowerbound_kim<E>(&self,query:&[D],events:E,best_so_far:T)->T
+    where E:Fn(usize)->D{
+        let dist = &self.dist;
+        let len = query.len();
+        let mut lb = dist(&query[0],&events(0));
+        if len == 1 {
+            return lb;
+        }
+        lb = lb + dist(&query[len-1],&events(len-1));
+        if len < 4 || lb > best_so_far{
+            return lb;
+        }
+        let (q0,q1,c0,c1) = (query[0],query[1],events(0),events(1));
+        lb = lb + dist(&q1,&c0).min(dist(&q0,&c1)).min(dist(&q1,&c1));
+        if lb > best_so_far{
+            return lb;
+        }
+        let (q0,q1,c0,c1) = (query[len-1],query[len-2],events(len-1),events(len-2));
+        lb + dist(&q1,&c0).min(dist(&q0,&c1)).min(dist(&q1,&c1))
+    }
+    // LB_Keogh of the events to the envelope, with reordered early abandoning.
+    // each[i] is set to the contribution of the i-th position.
+    fn lowerbound_keogh<E,L,U>(&self,events:E,lower:L,upper:U,order:&[usize],
+                               best_so_far:T,each:&mut [T])->T
+    where E:Fn(usize)->D, L:Fn(usize)->D, U:Fn(usize)->D{
+        for x in each.iter_mut(){
+            *x = T::zero();
+        }
         let mut lb = T::zero();
         for &i in order{
-//            eprint!("{},",i);
             if lb > best_so_far{
                 return lb
-            }else{
-                lb = lb + if events[i] < lower[i]{
-                    (self.dist)(&events[i],&lower[i])
-                }else if events[i] > upper[i]{
-                    (self.dist)(&events[i],&upper[i])
-                }else{
-                    T::zero()
-                };
             }
-        }
-        lb
-    }
-    fn cumulative_lower_bound(&self,events:&[D],lower:&[D],upper:&[D],
-                              lower_bound:T)->Vec<T>{
-        // clb[i] is sum of the difference from i to the end.
-        // First, compute lb, then compute clb[i] each.
-        let mut lb = lower_bound;
-        let mut clb = Vec::with_capacity(events.len());
-        for i in 0..events.len(){
-            lb = lb - if events[i] < lower[i]{
-                (self.dist)(&events[i],&lower[i])
-            }else if events[i] > upper[i]{
-                (self.dist)(&events[i],&upper[i])
+            let (x,lower,upper) = (events(i),lower(i),upper(i));
+            each[i] = if x < lower{
+                (self.dist)(&x,&lower)
+            }else if x > upper{
+                (self.dist)(&x,&upper)
             }else{
                 T::zero()
             };
-            clb.push(lb);
+            lb = lb + each[i];
         }
-        clb
+        lb
     }
-    /// sub banded dynamic time warping using ucr optimization
+    /// sub banded dynamic time warping using ucr optimization.
+    /// cumulative_lower_bound[i] should be a lower bound of the cost of the query (or the window) from
+    /// the i-th position to the end, and have query.len()+1 elements.
+    /// It returns None when the computation is abandoned because the score exceeds best_so_far.
     pub fn chiba_dtw_with_lower_bound(&self,query:&[D],subref:&[D],
-                                  cumulative_lower_bound:&[T],best_so_far:T,
-                                      lb_m:&mut u32)->T{
+                                      cumulative_lower_bound:&[T],best_so_far:T)->Option<T>{
         // dynamic time warping. Not preserving traceback path.
         debug_assert!(query.len()==subref.len(),"r{},q{}",subref.len(),query.len());
-//        eprintln!("{},{}",query.len(),subref.len());
+        debug_assert!(cumulative_lower_bound.len()==query.len()+1);
         let len = query.len();
-        let band = self.bandwidth;
+        let band = self.bandwidth.min(len-1);
+        // previous[j] and current[j] are the scores of the cells (i,i+j-band).
         let mut previous = vec![T::infinity();2*band+1];
         let mut current = vec![T::infinity();2*band+1];
-        //        eprintln!("{:?}\n{:?}",previous.len(),current.len());
-        previous[band] = T::zero();
-        for i in 1..len+1{
+        for i in 0..len{
             let mut score = T::infinity();
             for j in 0..2*band+1{
-                if i + j <= band || len + band <= i + j -1{
+                if i + j < band || len + band <= i + j{
+                    current[j] = T::infinity();
                     continue
+                }
+                let col = i + j - band;
+                let min = if i == 0 && col == 0{
+                    T::zero()
                 }else{
-                    //eprint!("{},{}",i,j);
                     // gap transition on query
                     let gap = if j==0 {T::infinity()}else{current[j-1]};
                     // delete transition on query
                     let del = if j ==2*band {T::infinity()}else{previous[j+1]};
                     // match transision on query
                     let mat =  previous[j];
-                    //eprintln!("-> score get");
-                    current[j] = (self.dist)(&query[i-1],
-                                             &subref[i+j-band-1]) +
-                        if gap <= del && gap<= mat{
-                            gap
-                        }else if del <= gap && del <= mat{
-                            del
-                        }else{
-                            mat
-                        };
-                    if current[j] < score{
-                        score = current[j];
-                    }
+                    mat.min(gap.min(del))
+                };
+                let cost = (self.dist)(&query[i],&subref[col]);
+                current[j] = if min.is_infinite(){ min } else { min + cost };
+                if current[j] < score{
+                    score = current[j];
                 }
             }
-            if score+cumulative_lower_bound[i-1]>best_so_far{
+            // The remaining path passes the rows after i and the columns after i+band.
+            let rest = cumulative_lower_bound[(i+band+1).min(len)];
+            if score+rest>best_so_far{
                 // early abandon
-                *lb_m+=1;
-                return score+cumulative_lower_bound[i-1]
+                return None
+            }
+            std::mem::swap(&mut previous,&mut current);
+        }
+        Some(previous[band])
+    }
+}
+
+impl<D,T,F> DynamicTimeWarping<D,T,F>
+    where F:Fn(&D,&D)->T, T:Float,D:Float{
+    /// Subsequence banded dynamic time warping between the z-normalized query and
+    /// the z-normalized windows of the reference (UCR suite).
+    /// The mean and the standard deviation of each window are updated online.
+    /// It returns the optimal score, the start position of the window, and the pruning statistics.
+    pub fn normalized_dtw(&self,query:&[D])->(T,usize,PruningStats){
+        let query = z_normalize(query);
+        let order = order_by_magnitude(&query);
+        let (mut best_so_far,mut best_position) = (T::infinity(),0);
+        let stats = self.scan(&query,&order,ZNormalize::new(),|pos,score|{
+            if score < best_so_far{
+                best_so_far = score;
+                best_position = pos;
+            }
+            best_so_far
+        });
+        (best_so_far,best_position,stats)
+    }
+    /// Same as `top_k`, between the z-normalized query and the z-normalized windows.
+    /// The paths are computed on the z-normalized series.
+    pub fn normalized_top_k(&self,query:&[D],k:usize,exclusion:usize)->(Vec<Alignment<T>>,PruningStats){
+        let query = z_normalize(query);
+        let order = order_by_magnitude(&query);
+        let (starts,stats) = self.scan_top_k(&query,&order,ZNormalize::new(),k,exclusion);
+        let len = query.len();
+        let hits = starts.into_iter()
+            .map(|start| self.path(&query,&z_normalize(&self.reference[start..start+len]),start))
+            .collect();
+        (hits,stats)
+    }
+    /// Euclidean distance, i.e., the sum of dist over the positions, between the z-normalized query and
+    /// the z-normalized windows of the reference, with reordered early abandoning.
+    /// It returns the optimal score, the start position of the window, and the pruning statistics.
+    pub fn normalized_euclidean(&self,query:&[D])->(T,usize,PruningStats){
+        let mut stats = PruningStats::default();
+        let len = query.len();
+        if len == 0 || self.reference.len() < len{
+            return (T::infinity(),0,stats);
+        }
+        let query = z_normalize(query);
+        let order = order_by_magnitude(&query);
+        let mut window = ZNormalize::new();
+        let mut best_so_far :T= T::infinity();
+        let mut best_position = 0;
+        for pos in 0..self.reference.len() - len + 1{
+            stats.windows += 1;
+            window.slide(&self.reference,pos,len);
+            let subref = &self.reference[pos..pos+len];
+            let mut score = T::zero();
+            for &i in &order{
+                if score > best_so_far{
+                    break;
+                }
+                score = score + (self.dist)(&query[i],&window.apply(subref[i]));
+            }
+            if score > best_so_far{
+                stats.abandoned += 1;
+                continue;
+            }
+            stats.completed += 1;
+            if score < best_so_far{
+                best_so_far = score;
+                best_position = pos;
             }
-//            eprintln!();
-            previous.clear();
-            previous.append(&mut current);
-            current.append(&mut vec![T::infinity();len]);
         }
-        previous[band]
+        (best_so_far,best_position,stats)
     }
 }
 
+impl<D,T,F> DynamicTimeWarping<D,T,F>
+    where F:Fn(&D,&D)->T, T:Float,D:IndexElement{
+    /// Write the reference, its envelopes, and the band width to a file,
+    /// so that they can be loaded by `load` without recomputation.
+    /// The file consists of a 32-byte header (magic number "DTWINDEX", format version,
+    /// element size, band width, and length) followed by the reference, the lower envelope,
+    /// and the upper envelope, each padded to 8 bytes. All values are little endian.
+    pub fn save<P:AsRef<Path>>(&self,path:P)->Result<(),IndexError>{
+        let mut w = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let len = self.reference.len();
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&(D::SIZE as u32).to_le_bytes())?;
+        w.write_all(&(self.bandwidth as u64).to_le_bytes())?;
+        w.write_all(&(len as u64).to_le_bytes())?;
+        let section = section_size(len,D::SIZE).expect("the reference fits in memory");
+        let padding = vec![0u8;section - len * D::SIZE];
+        for series in &[&self.reference,&self.lower_envelope,&self.upper_envelope]{
+            for x in series.iter(){
+                x.write_le(&mut w)?;
+            }
+            w.write_all(&padding)?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+    /// Memory-map an index written by `save`.
+    /// It returns Err(IndexError::BandwidthMismatch{..}) if the stored band width
+    /// differs from the given one, as the envelopes depend on it.
+    /// The file should not be modified while the returned struct is alive.
+    pub fn load<P:AsRef<Path>>(path:P,dist:F,bandwidth:usize)->Result<Self,IndexError>{
+        if cfg!(target_endian = "big"){
+            return Err(IndexError::Io(std::io::Error::new(std::io::ErrorKind::Unsupported,
+                                                          "index files can not be mapped on big endian platforms")));
+        }
+        let file = std::fs::File::open(path)?;
+        // Safety: the map is read only, and the file should not be modified while it is mapped.
+        let map = Arc::new(unsafe{ Mmap::map(&file)? });
+        if map.len() < HEADER_SIZE || &map[0..8] != MAGIC{
+            return Err(IndexError::InvalidFormat);
+        }
+        let read_u32 = |i:usize| u32::from_le_bytes([map[i],map[i+1],map[i+2],map[i+3]]);
+        let read_u64 = |i:usize| (read_u32(i) as u64) | ((read_u32(i+4) as u64) << 32);
+        let version = read_u32(8);
+        if version != VERSION{
+            return Err(IndexError::UnsupportedVersion(version));
+        }
+        let size = read_u32(12) as usize;
+        if size != D::SIZE{
+            return Err(IndexError::ElementMismatch{expected:D::SIZE,found:size});
+        }
+        let stored = read_u64(16) as usize;
+        if stored != bandwidth{
+            return Err(IndexError::BandwidthMismatch{stored,requested:bandwidth});
+        }
+        // The length comes from the file, thus the sizes are checked for overflow
+        // before they are compared with the file size.
+        let len = usize::try_from(read_u64(24)).map_err(|_| IndexError::InvalidFormat)?;
+        let section = section_size(len,D::SIZE).ok_or(IndexError::InvalidFormat)?;
+        let total = section.checked_mul(3).and_then(|x| x.checked_add(HEADER_SIZE));
+        if total != Some(map.len()){
+            return Err(IndexError::InvalidFormat);
+        }
+        let mapped = |offset:usize| Series::Mapped{map:map.clone(),offset,len,element:std::marker::PhantomData};
+        Ok(DynamicTimeWarping{reference:mapped(HEADER_SIZE),
+                              dist,
+                              lower_envelope:mapped(HEADER_SIZE + section),
+                              upper_envelope:mapped(HEADER_SIZE + 2 * section),
+                              bandwidth})
+    }
+}
 
+/// Batch search. The struct is shared by the threads, thus it is Sync
+/// as long as the reference and dist are Sync.
+impl<D,T,F> DynamicTimeWarping<D,T,F>
+    where F:Fn(&D,&D)->T+Sync, T:Float+Send, D:Copy+PartialOrd+Sync{
+    /// Apply search to each query in parallel, and return the results in the order of the queries.
+    /// The queries are distributed to the given number of threads one by one,
+    /// and 0 means the available parallelism of the machine.
+    /// For example, `batch(&queries,0,|dtw,q| dtw.top_k(q,3,q.len()))`.
+    pub fn batch<Q,R,S>(&self,queries:&[Q],threads:usize,search:S)->Vec<R>
+    where Q:AsRef<[D]>+Sync, R:Send, S:Fn(&Self,&[D])->R+Sync{
+        let threads = if threads == 0{
+            std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+        }else{
+            threads
+        }.min(queries.len()).max(1);
+        let next = std::sync::atomic::AtomicUsize::new(0);
+        let mut results:Vec<(usize,R)> = std::thread::scope(|scope|{
+            let workers:Vec<_> = (0..threads).map(|_| scope.spawn(||{
+                let mut results = vec![];
+                loop{
+                    let i = next.fetch_add(1,std::sync::atomic::Ordering::Relaxed);
+                    if i >= queries.len(){
+                        break results;
+                    }
+                    results.push((i,search(self,queries[i].as_ref())));
+                }
+            })).collect();
+            workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+        });
+        results.sort_by_key(|&(i,_)| i);
+        results.into_iter().map(|(_,r)| r).collect()
+    }
+    /// `dtw` for each query in parallel. See `batch`.
+    pub fn batch_dtw<Q>(&self,queries:&[Q],threads:usize)->Vec<(T,usize)>
+    where Q:AsRef<[D]>+Sync{
+        self.batch(queries,threads,|dtw,query|{
+            let (score,position,_) = dtw.dtw_with_stats(query);
+            (score,position)
+        })
+    }
+}
+
+// Take windows greedily from the best one so that the starts of any two windows are
+// at least exclusion apart, up to k windows. Ties are broken by the start position.
+fn separated<T:Float>(windows:&mut [(T,usize)],k:usize,exclusion:usize)->Vec<(T,usize)>{
+    windows.sort_by(|a,b| a.partial_cmp(b).unwrap());
+    let mut taken:Vec<(T,usize)> = Vec::with_capacity(k);
+    for &(score,start) in windows.iter(){
+        if taken.len() >= k{
+            break;
+        }
+        if taken.iter().all(|&(_,s)| s + exclusion <= start || start + exclusion <= s){
+            taken.push((score,start));
+        }
+    }
+    taken
+}
+
+// Z-normalize the series in the same way as the windows of the reference.
+fn z_normalize<D:Float>(events:&[D])->Vec<D>{
+    let mut window = ZNormalize::new();
+    if !events.is_empty(){
+        window.slide(events,0,events.len());
+    }
+    events.iter().map(|&x| window.apply(x)).collect()
+}
+
+// The positions in the descending order of the absolute value.
+// For a z-normalized series, they tend to contribute most to the distance.
+fn order_by_magnitude<D:Float>(events:&[D])->Vec<usize>{
+    let mut order:Vec<_> = (0..events.len()).collect();
+    order.sort_by(|&a,&b| events[b].abs().partial_cmp(&events[a].abs()).unwrap());
+    order
+}
@@ -46,9 +46,15 @@ pub enum Method {
     SubHill,
 }
 
-///parse mode. Currently parse only Sub, Chiba and Scouting dtw.
+///parse mode. Currently parse only Sub, SubChiba, Chiba and Scouting dtw.
 pub fn get_mode(mode: &str) -> std::result::Result<super::Mode, String> {
-    if mode.starts_with("Sub") {
+    if mode.starts_with("SubChiba") {
+        let bandwidth: usize = match mode.split(',').nth(1).and_then(|e| e.parse().ok()) {
+            Some(b) => b,
+            None => return Err("Given subchiba but couldn't parse bandwidth correctly".to_string()),
+        };
+        Ok(super::Mode::SubSakoeChiba(bandwidth))
+    } else if mode.starts_with("Sub") {
         Ok(super::Mode::Sub)
     } else if mode.starts_with("Chiba") {
         let bandwidth: usize = match mode.split(',').nth(1).and_then(|e| e.parse().ok()) {
@@ -149,7 +155,7 @@ pub fn padding_fast_dtw<F>(
     dist: &F,
     raidus: usize,
     is_sub: bool,
-) -> Result<(f32, (Vec<usize>, Vec<usize>), usize), String>
+) -> Result<super::Alignment<f32>, super::DtwError>
 where
     F: Fn(&f32, &f32) -> f32,
 {
@@ -175,39 +181,27 @@ pub fn optimal_dtw(
     };
     match method {
         &Method::SubHill => super::dtw(&query, &reference, super::Mode::Sub, &hill)
-            .map(|e| e.0)
+            .map(|e| e.score)
             .ok(),
         &Method::SubNormal => super::dtw(&query, &reference, super::Mode::Sub, &normal)
-            .map(|e| e.0)
+            .map(|e| e.score)
             .ok(),
-        &Method::ChibaHill | &Method::ChibaNormal => {
-            let mut skip_count = 0;
-            let mut best_so_far = 10000.;
-            let is_hill = match method {
-                &Method::ChibaHill => true,
-                _ => false,
-            };
-            for subref in reference.windows(query.len()) {
-                if skip_count > 0 {
-                    skip_count -= 1;
-                    continue;
-                }
-                let res = if is_hill {
-                    super::dtw(&query, subref, super::Mode::SakoeChiba(BANDWIDTH), &hill)
-                } else {
-                    super::dtw(&query, subref, super::Mode::SakoeChiba(BANDWIDTH), &normal)
-                };
-                if let Ok(res) = res {
-                    if res.0 < best_so_far {
-                        best_so_far = res.0;
-                        skip_count += BANDWIDTH / 2;
-                    } else {
-                        skip_count += query.len() / 10;
-                    }
-                }
-            }
-            Some(best_so_far)
-        }
+        &Method::ChibaHill => super::dtw(
+            &query,
+            &reference,
+            super::Mode::SubSakoeChiba(BANDWIDTH),
+            &hill,
+        )
+        .map(|e| e.score)
+        .ok(),
+        &Method::ChibaNormal => super::dtw(
+            &query,
+            &reference,
+            super::Mode::SubSakoeChiba(BANDWIDTH),
+            &normal,
+        )
+        .map(|e| e.score)
+        .ok(),
     }
 }
 
@@ -266,12 +260,12 @@ pub fn folded_optimal_dtw(
         &Method::SubHill => {
             super::dtw(&query, &reference[start..end], super::Mode::Sub, &hill)
                 .unwrap()
-                .0
+                .score
         }
         &Method::SubNormal => {
             super::dtw(&query, &reference[start..end], super::Mode::Sub, &normal)
                 .unwrap()
-                .0
+                .score
         }
         &Method::ChibaHill => chiba_optimal_dtw(
             &query,
@@ -302,9 +296,14 @@ fn optimal_dtw_with_location(
         &Prep::Flat => super::histgram_modify(&query, cdf),
         &Prep::Normal => query.clone(),
     };
-    let (score, _, location) = match method {
-        &Method::SubHill => super::dtw(&query, &reference, super::Mode::Sub, &hill).unwrap(),
-        &Method::SubNormal => super::dtw(&query, &reference, super::Mode::Sub, &normal).unwrap(),
+    let location = |aln: super::Alignment<f32>| (aln.score, aln.reference_start().unwrap());
+    let (score, location) = match method {
+        &Method::SubHill => {
+            location(super::dtw(&query, &reference, super::Mode::Sub, &hill).unwrap())
+        }
+        &Method::SubNormal => {
+            location(super::dtw(&query, &reference, super::Mode::Sub, &normal).unwrap())
+        }
         &Method::ChibaHill | &Method::ChibaNormal => {
             let querysize = query.len();
             let refsize = reference.len();
@@ -315,7 +314,7 @@ fn optimal_dtw_with_location(
             while offset + querysize < refsize {
                 let subref = &reference[offset..offset + querysize];
                 let subref = &padding_reference(subref)[0..querysize];
-                let (score, t, sub_start) = match method {
+                let score = match method {
                     &Method::ChibaHill => {
                         super::dtw(&query, subref, super::Mode::SakoeChiba(bandwidth), &hill)
                             .unwrap()
@@ -325,9 +324,10 @@ fn optimal_dtw_with_location(
                             .unwrap()
                     }
                     _ => unreachable!(),
-                };
+                }
+                .score;
                 if opt > score {
-                    result = Some((score, t, offset + sub_start));
+                    result = Some((score, offset));
                     opt = score;
                     offset += bandwidth / 2;
                 } else {
@@ -343,76 +343,55 @@ fn optimal_dtw_with_location(
     Some((score, location))
 }
 
-///fast mode
+///fast mode. The best window of the reference is searched by Mode::SubSakoeChiba.
 pub fn chiba_skipping_dtw(
     query: &[f32],
     reference: &[f32],
     mode: super::Mode,
     is_hill: bool,
 ) -> Option<f32> {
-    let mut best_so_far = 100000.;
-    let mut skip_count = 0;
-    let querysize = query.len();
-    let bandwidth = match mode {
-        super::Mode::SakoeChiba(b) => b,
+    let mode = match mode {
+        super::Mode::SakoeChiba(b) | super::Mode::SubSakoeChiba(b) => super::Mode::SubSakoeChiba(b),
         _ => {
             eprintln!("not valid input");
             return None;
         }
     };
-
-    for subref in reference.windows(query.len()) {
-        if skip_count > 0 {
-            skip_count -= 1;
-            continue;
-        }
-        let res = if is_hill {
-            super::dtw(query, subref, mode, &hill)
-        } else {
-            super::dtw(query, subref, mode, &normal)
-        };
-        if let Ok(res) = res {
-            if res.0 < best_so_far {
-                best_so_far = res.0;
-                skip_count = bandwidth / 2;
-            } else {
-                skip_count = querysize / 10; //2*bandwidth;
-            }
-        }
+    if is_hill {
+        super::dtw(query, reference, mode, &hill)
+    } else {
+        super::dtw(query, reference, mode, &normal)
     }
-    Some(best_so_far)
+    .map(|e| e.score)
+    .ok()
 }
 
-/// Debug mode,too slow
+/// Debug mode. The reference is randomly padded before searched by Mode::SubSakoeChiba.
+/// The whole reference is padded once, so a window of the padded reference may cover
+/// fewer samples of the original reference than the query length.
+/// If the padded reference is still shorter than the query, it is aligned as a whole.
 pub fn chiba_optimal_dtw(
     query: &[f32],
     reference: &[f32],
     mode: &super::Mode,
     is_hill: bool,
 ) -> Option<f32> {
-    let querysize = query.len();
     let bandwidth = match mode {
-        &super::Mode::SakoeChiba(b) => b,
+        &super::Mode::SakoeChiba(b) | &super::Mode::SubSakoeChiba(b) => b,
         _ => {
             eprintln!("not valid input");
             return None;
         }
     };
-    let mut opt = 1000000.;
-    for subref in reference.windows(querysize) {
-        let subref = &padding_reference(&subref)[0..querysize];
-        let result = if is_hill {
-            super::dtw(&query, subref, super::Mode::SakoeChiba(bandwidth), &hill)
-        } else {
-            super::dtw(&query, subref, super::Mode::SakoeChiba(bandwidth), &normal)
-        };
-        if let Ok((score, _, _)) = result {
-            if opt > score {
-                opt = score;
-            }
-        }
+    let reference = padding_reference(reference);
+    let mode = super::Mode::SubSakoeChiba(bandwidth);
+    if is_hill {
+        super::dtw(query, &reference, mode, &hill)
+    } else {
+        super::dtw(query, &reference, mode, &normal)
     }
-    Some(opt)
+    .map(|e| e.score)
+    .ok()
 }
 
 ///optimal dtw. Note this function does not need any "util" enum.
@@ -423,23 +402,82 @@ pub fn dtw_wrapper(
     metric: &str,
     prep: &Option<Vec<f32>>,
     threshold: &Option<f32>,
+) -> Option<f32> {
+    wrapper(query, reference, mode, metric, prep, threshold, &None)
+}
+
+/// dtw_wrapper with the normalized score.
+/// The threshold is compared with the normalized score,
+/// and the normalized score is returned. Modes without the warping path
+/// (Scouting) return None for Normalization::PathLength.
+pub fn dtw_wrapper_normalized(
+    query: &[f32],
+    reference: &[f32],
+    mode: &super::Mode,
+    metric: &str,
+    prep: &Option<Vec<f32>>,
+    threshold: &Option<f32>,
+    normalization: super::Normalization,
+) -> Option<f32> {
+    let normalization = Some(normalization);
+    wrapper(
+        query,
+        reference,
+        mode,
+        metric,
+        prep,
+        threshold,
+        &normalization,
+    )
+}
+
+fn wrapper(
+    query: &[f32],
+    reference: &[f32],
+    mode: &super::Mode,
+    metric: &str,
+    prep: &Option<Vec<f32>>,
+    threshold: &Option<f32>,
+    normalization: &Option<super::Normalization>,
 ) -> Option<f32> {
     use super::Mode;
+    use super::Normalization;
     let query = match prep {
         &Some(ref cdf) => super::histgram_modify(query, cdf),
         &None => query.to_vec(),
     };
     let is_hill = metric == "Hill" || metric == "hill" || metric == "HILL";
+    let finish = |aln: super::Alignment<f32>| match normalization {
+        &Some(by) => aln.normalized(by),
+        &None => Some(aln.score),
+    };
+    // The threshold for the raw score. The path is at most query.len() + reference.len() long.
+    let raw_threshold = threshold.map(|t| match normalization {
+        &Some(Normalization::QueryLength) => t * query.len() as f32,
+        &Some(Normalization::PathLength) => t * (query.len() + reference.len()) as f32,
+        &None => t,
+    });
     match mode {
-        &Mode::Sub | &Mode::QuickSub => match threshold {
-            &Some(threshold) => {
+        &Mode::Sub | &Mode::QuickSub if normalization.is_some() => {
+            let by = normalization.unwrap();
+            let threshold = threshold.unwrap_or(std::f32::INFINITY);
+            if is_hill {
+                super::thresholddtw_normalized(&query, &reference, &hill, threshold, by)
+            } else {
+                super::thresholddtw_normalized(&query, &reference, &normal, threshold, by)
+            }
+            .map(|e| e.score)
+            .ok()
+        }
+        &Mode::Sub | &Mode::QuickSub => match raw_threshold {
+            Some(threshold) => {
                 if is_hill {
                     super::thresholddtw(&query, &reference, &hill, threshold)
                 } else {
                     super::thresholddtw(&query, &reference, &normal, threshold)
                 }
             }
-            &None => {
+            None => {
                 if is_hill {
                     super::dtw(&query, &reference, mode.clone(), &hill)
                 } else {
@@ -447,10 +485,10 @@ pub fn dtw_wrapper(
                 }
             }
         }
-        .map(|e| e.0)
-        .ok(),
-        &Mode::Scouting(scouts, packs) => match threshold {
-            &Some(threshold) => {
+        .ok()
+        .and_then(finish),
+        &Mode::Scouting(scouts, packs) => match raw_threshold {
+            Some(threshold) => {
                 if is_hill {
                     super::scouting_threshold_dtw(
                         &query,
@@ -471,7 +509,7 @@ pub fn dtw_wrapper(
                     )
                 }
             }
-            &None => {
+            None => {
                 if is_hill {
                     super::dtw(&query, &reference, mode.clone(), &hill)
                 } else {
@@ -479,35 +517,22 @@ pub fn dtw_wrapper(
                 }
             }
         }
-        .map(|e| e.0)
-        .ok(),
-        &Mode::SakoeChiba(b) => {
-            let mut skip_count = 0;
-            let mut best_so_far = 1000000.;
-            for subref in reference.windows(query.len()) {
-                if skip_count > 0 {
-                    skip_count -= 1;
-                    continue;
-                }
-                let res = if is_hill {
-                    super::dtw(&query, subref, mode.clone(), &hill)
-                } else {
-                    super::dtw(&query, subref, mode.clone(), &normal)
-                };
-                if let Ok(res) = res {
-                    if res.0 < best_so_far {
-                        best_so_far = res.0;
-                        skip_count += b;
-                    } else {
-                        skip_count += query.len() / 10;
-                    }
-                }
+        .ok()
+        .and_then(finish),
+        // The best window of the reference, as Mode::SubSakoeChiba.
+        &Mode::SakoeChiba(b) | &Mode::SubSakoeChiba(b) => {
+            let mode = Mode::SubSakoeChiba(b);
+            if is_hill {
+                super::dtw(&query, &reference, mode, &hill)
+            } else {
+                super::dtw(&query, &reference, mode, &normal)
             }
-            Some(best_so_far)
+            .ok()
+            .and_then(finish)
         }
         &Mode::FastSub(r) => super::fast_dtw(&query, &reference, &normal, r, true)
-            .map(|e| e.0)
-            .ok(),
+            .ok()
+            .and_then(finish),
         _ => None,
     }
 }